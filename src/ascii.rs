@@ -0,0 +1,167 @@
+// Line oriented driver for Intcode programs that talk ASCII, like days 17, 21 and 25.
+//
+// Output is collected as text until the program asks for input or halts. Values outside the
+// ASCII range are not text, they stop reading and are returned as Reply::Value.
+
+use crate::intmachine::{IntMachine, IntcodeError, Memory, Status, Word};
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Reply {
+    // The program waits for input after printing the text
+    Prompt(String),
+    // The program halted after printing the text
+    Halted(String),
+    // A non ASCII value, with the text printed before it
+    Value(String, Word),
+    // The text read_at_most got up to its limit, the program has more to say
+    Truncated(String),
+}
+
+impl Reply {
+    pub fn text(&self) -> &str {
+        return match self {
+            Reply::Prompt(text) | Reply::Halted(text) | Reply::Value(text, _) | Reply::Truncated(text) => text,
+        }
+    }
+}
+
+pub fn is_ascii(value: Word) -> bool {
+    return (0..128).contains(&value);
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AsciiMachine {
+    machine: IntMachine,
+    // Output that tripped the limit of read_at_most, the next read starts with it
+    pending: Option<Word>,
+}
+
+impl AsciiMachine {
+    pub fn new(program: &Memory) -> AsciiMachine {
+        return AsciiMachine::from_machine(IntMachine::new(program));
+    }
+
+    pub fn from_machine(machine: IntMachine) -> AsciiMachine {
+        return AsciiMachine { machine, pending: None };
+    }
+
+    pub fn machine(&self) -> &IntMachine {
+        return &self.machine;
+    }
+
+    pub fn machine_mut(&mut self) -> &mut IntMachine {
+        return &mut self.machine;
+    }
+
+    pub fn is_halted(&self) -> bool {
+        return self.machine.is_halted();
+    }
+
+    // Queues the line followed by a newline, read by the program when it asks for input
+    pub fn send_line(&mut self, line: &str) {
+        for c in line.chars() {
+            self.machine.push_input(c as Word);
+        }
+        self.machine.push_input('\n' as Word);
+    }
+
+    pub fn send_lines(&mut self, lines: &[&str]) {
+        for line in lines {
+            self.send_line(line);
+        }
+    }
+
+    // Runs until the program needs more input than has been sent, halts or outputs a non ASCII value
+    pub fn read_until_prompt(&mut self) -> Result<Reply, IntcodeError> {
+        return self.read(None);
+    }
+
+    // Like read_until_prompt, but stops with Reply::Truncated once the program printed more than
+    // limit characters. Reading can go on after that, from the output that went over the limit.
+    pub fn read_at_most(&mut self, limit: usize) -> Result<Reply, IntcodeError> {
+        return self.read(Some(limit));
+    }
+
+    fn read(&mut self, limit: Option<usize>) -> Result<Reply, IntcodeError> {
+        let mut text = String::new();
+        loop {
+            let status = match self.pending.take() {
+                Some(data) => Status::Output(data),
+                None => self.machine.run()?,
+            };
+            match status {
+                Status::Output(data) if matches!(limit, Some(limit) if text.len() >= limit) => {
+                    self.pending = Some(data);
+                    return Ok(Reply::Truncated(text));
+                },
+                Status::Output(data) if is_ascii(data) => text.push(char::from(data as u8)),
+                Status::Output(data) => return Ok(Reply::Value(text, data)),
+                Status::NeedsInput => return Ok(Reply::Prompt(text)),
+                Status::Halted => return Ok(Reply::Halted(text)),
+            }
+        }
+    }
+
+    // Reads until the program halts or asks for input, returning all text and non ASCII values
+    pub fn read_all(&mut self) -> Result<(String, Vec<Word>), IntcodeError> {
+        let mut text = String::new();
+        let mut values = vec![];
+        loop {
+            match self.read_until_prompt()? {
+                Reply::Value(t, value) => {
+                    text.push_str(&t);
+                    values.push(value);
+                },
+                reply => {
+                    text.push_str(reply.text());
+                    return Ok((text, values));
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ascii::{AsciiMachine, Reply};
+    use crate::assembler::assemble;
+
+    #[test]
+    fn test_echo() {
+        // Prints a prompt, echoes one line and outputs its length plus 1000
+        let program = assemble("
+                out  62
+                out  10
+        loop:   in   [c]
+                out  [c]
+                eq   [c], 10, [done]
+                jt   [done], end
+                add  [n], 1, [n]
+                jt   1, loop
+        end:    add  [n], 1000, [n]
+                out  [n]
+                hlt
+        c:      data 0
+        n:      data 0
+        done:   data 0
+        ").unwrap();
+        let mut machine = AsciiMachine::new(&program);
+        assert_eq!(machine.read_until_prompt(), Ok(Reply::Prompt(">\n".to_string())));
+        machine.send_line("hi");
+        assert_eq!(machine.read_until_prompt(), Ok(Reply::Value("hi\n".to_string(), 1002)));
+        assert_eq!(machine.read_until_prompt(), Ok(Reply::Halted(String::new())));
+        assert!(machine.is_halted());
+
+        let mut machine = AsciiMachine::new(&program);
+        machine.send_lines(&["abc"]);
+        assert_eq!(machine.read_all(), Ok((">\nabc\n".to_string(), vec![1003])));
+
+        let mut machine = AsciiMachine::new(&program);
+        machine.send_line("abcdef");
+        assert_eq!(machine.read_at_most(4), Ok(Reply::Truncated(">\nab".to_string())));
+        assert_eq!(machine.read_until_prompt(), Ok(Reply::Value("cdef\n".to_string(), 1006)));
+        let mut machine = AsciiMachine::new(&program);
+        machine.send_line("abc");
+        assert_eq!(machine.read_at_most(10), Ok(Reply::Value(">\nabc\n".to_string(), 1003)));
+    }
+}
@@ -0,0 +1,330 @@
+// Assembler for a small textual Intcode syntax.
+//
+//   ; comment
+//   start:  in   [x]            ; position parameter
+//           add  [x], 1, [x]    ; plain operands are immediate
+//           arb  -2
+//           out  [rb+1]         ; relative parameter, [rb-x+1] is rb - x + 1
+//           jt   1, start       ; labels can be used anywhere a number can
+//           hlt
+//   x:      data 0, start+2, -1
+//
+// Mnemonics: add, mul, in, out, jt, jf, lt, eq, arb, hlt
+
+use crate::intmachine::{Instruction, Parameter, Memory, Word};
+use crate::intmachine::Instruction::{Halt, Add, Multiply, Input, Output, JumpIfTrue, JumpIfFalse, LessThan, Equals, AdjustRelativeBase};
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct AssemblyError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AssemblyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum Expr {
+    Number(Word),
+    Label(String, Word),
+    // The offset minus the label, for [rb-label]
+    NegatedLabel(String, Word),
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Mode {
+    Pos,
+    Imm,
+    Rel,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct Operand {
+    mode: Mode,
+    expr: Expr,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum Statement {
+    Instruction(String, Vec<Operand>),
+    Data(Vec<Expr>),
+}
+
+struct Line {
+    number: usize,
+    statement: Statement,
+}
+
+pub fn assemble_file(filename :&str) -> Result<Memory, AssemblyError> {
+    let s = std::fs::read_to_string(filename).map_err(|e| AssemblyError { line: 0, message: e.to_string() })?;
+    return assemble(&s);
+}
+
+pub fn assemble(source: &str) -> Result<Memory, AssemblyError> {
+    let mut labels: HashMap<String, Word> = HashMap::new();
+    let mut lines = vec![];
+    let mut address: Word = 0;
+
+    // First pass, parse and assign addresses to labels
+    for (i, raw) in source.lines().enumerate() {
+        let number = i + 1;
+        let error = |message: String| AssemblyError { line: number, message };
+        let mut text = match raw.find(';') {
+            Some(p) => &raw[..p],
+            None => raw,
+        }.trim();
+
+        while let Some(p) = text.find(':') {
+            let label = text[..p].trim();
+            if !is_identifier(label) {
+                return Err(error(format!("Invalid label: {:?}", label)));
+            }
+            if labels.insert(label.to_string(), address).is_some() {
+                return Err(error(format!("Duplicate label: {}", label)));
+            }
+            text = text[p + 1..].trim();
+        }
+        if text.is_empty() {
+            continue;
+        }
+
+        let statement = parse_statement(text).map_err(error)?;
+        address += match &statement {
+            Statement::Instruction(_, operands) => 1 + operands.len() as Word,
+            Statement::Data(values) => values.len() as Word,
+        };
+        lines.push(Line { number, statement });
+    }
+
+    // Second pass, resolve labels and emit
+    let mut memory = vec![];
+    for line in lines {
+        let error = |message: String| AssemblyError { line: line.number, message };
+        match &line.statement {
+            Statement::Data(values) => {
+                for v in values {
+                    memory.push(resolve(v, &labels).map_err(error)?);
+                }
+            },
+            Statement::Instruction(mnemonic, operands) => {
+                let mut params = vec![];
+                for operand in operands {
+                    let value = resolve(&operand.expr, &labels).map_err(error)?;
+                    params.push(match operand.mode {
+                        Mode::Pos => Parameter::Pos(value),
+                        Mode::Imm => Parameter::Imm(value),
+                        Mode::Rel => Parameter::Rel(value),
+                    });
+                }
+                let instruction = build_instruction(mnemonic, &params).map_err(error)?;
+                memory.extend(instruction.encode());
+            },
+        }
+    }
+    return Ok(memory);
+}
+
+fn build_instruction(mnemonic: &str, params: &[Parameter]) -> Result<Instruction, String> {
+    let expected = match mnemonic {
+        "add" | "mul" | "lt" | "eq" => 3,
+        "jt" | "jf" => 2,
+        "in" | "out" | "arb" => 1,
+        "hlt" => 0,
+        _ => return Err(format!("Unknown mnemonic: {}", mnemonic)),
+    };
+    if params.len() != expected {
+        return Err(format!("{} takes {} operands, got {}", mnemonic, expected, params.len()));
+    }
+    let instruction = match mnemonic {
+        "add" => Add { op1: params[0], op2: params[1], dst: params[2] },
+        "mul" => Multiply { op1: params[0], op2: params[1], dst: params[2] },
+        "in" => Input { dst: params[0] },
+        "out" => Output { src: params[0] },
+        "jt" => JumpIfTrue { cond: params[0], target: params[1] },
+        "jf" => JumpIfFalse { cond: params[0], target: params[1] },
+        "lt" => LessThan { op1: params[0], op2: params[1], dst: params[2] },
+        "eq" => Equals { op1: params[0], op2: params[1], dst: params[2] },
+        "arb" => AdjustRelativeBase { op: params[0] },
+        _ => Halt,
+    };
+    if let Some(Parameter::Imm(_)) = instruction.destination() {
+        return Err(format!("{} can not write to an immediate operand", mnemonic));
+    }
+    return Ok(instruction);
+}
+
+fn parse_statement(text: &str) -> Result<Statement, String> {
+    let (head, rest) = match text.find(char::is_whitespace) {
+        Some(p) => (&text[..p], text[p..].trim()),
+        None => (text, ""),
+    };
+    let head = head.to_lowercase();
+    let args: Vec<&str> = if rest.is_empty() {
+        vec![]
+    } else {
+        rest.split(',').map(|a| a.trim()).collect()
+    };
+
+    if head == "data" {
+        if args.is_empty() {
+            return Err("data needs at least one value".to_string());
+        }
+        let values = args.iter().map(|a| parse_expr(a)).collect::<Result<Vec<Expr>, String>>()?;
+        return Ok(Statement::Data(values));
+    }
+    let operands = args.iter().map(|a| parse_operand(a)).collect::<Result<Vec<Operand>, String>>()?;
+    return Ok(Statement::Instruction(head, operands));
+}
+
+fn parse_operand(text: &str) -> Result<Operand, String> {
+    if !text.starts_with('[') {
+        return Ok(Operand { mode: Mode::Imm, expr: parse_expr(text)? });
+    }
+    if !text.ends_with(']') {
+        return Err(format!("Missing ']' in operand: {}", text));
+    }
+    let inner = text[1..text.len() - 1].trim();
+    if inner == "rb" {
+        return Ok(Operand { mode: Mode::Rel, expr: Expr::Number(0) });
+    }
+    if let Some(offset) = inner.strip_prefix("rb") {
+        let offset = offset.trim();
+        if let Some(offset) = offset.strip_prefix('+') {
+            return Ok(Operand { mode: Mode::Rel, expr: parse_expr(offset.trim())? });
+        }
+        if let Some(offset) = offset.strip_prefix('-') {
+            // Only the label is subtracted, an offset after it keeps its sign
+            let expr = match parse_expr(offset.trim()).map_err(|_| format!("Invalid relative operand: {}", text))? {
+                Expr::Number(v) => Expr::Number(-v),
+                Expr::Label(label, offset) => Expr::NegatedLabel(label, offset),
+                Expr::NegatedLabel(label, offset) => Expr::Label(label, offset),
+            };
+            return Ok(Operand { mode: Mode::Rel, expr });
+        }
+    }
+    return Ok(Operand { mode: Mode::Pos, expr: parse_expr(inner)? });
+}
+
+fn parse_expr(text: &str) -> Result<Expr, String> {
+    if let Ok(v) = text.parse::<Word>() {
+        return Ok(Expr::Number(v));
+    }
+    // label, label+offset or label-offset
+    let (label, offset) = match text.find(['+', '-']) {
+        Some(p) => {
+            let offset = text[p + 1..].trim().parse::<Word>().map_err(|_| format!("Invalid offset: {}", text))?;
+            let sign = if &text[p..p + 1] == "-" { -1 } else { 1 };
+            (text[..p].trim(), sign * offset)
+        },
+        None => (text, 0),
+    };
+    if !is_identifier(label) {
+        return Err(format!("Invalid value: {}", text));
+    }
+    return Ok(Expr::Label(label.to_string(), offset));
+}
+
+fn resolve(expr: &Expr, labels: &HashMap<String, Word>) -> Result<Word, String> {
+    return match expr {
+        Expr::Number(v) => Ok(*v),
+        Expr::Label(label, offset) => match labels.get(label) {
+            Some(address) => Ok(address + offset),
+            None => Err(format!("Undefined label: {}", label)),
+        },
+        Expr::NegatedLabel(label, offset) => match labels.get(label) {
+            Some(address) => Ok(offset - address),
+            None => Err(format!("Undefined label: {}", label)),
+        },
+    }
+}
+
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    return match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            s != "rb" && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        },
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::assembler::assemble;
+    use crate::intmachine::execute_with_result;
+
+    #[test]
+    fn test_encoding() {
+        assert_eq!(assemble("add [4], 3, [4]\nhlt").unwrap(), vec![1001, 4, 3, 4, 99]);
+        assert_eq!(assemble("mul [rb+1], [rb-2], [rb]").unwrap(), vec![22202, 1, -2, 0]);
+        assert_eq!(assemble("data 1, -2, 3\ndata 4").unwrap(), vec![1, -2, 3, 4]);
+    }
+
+    #[test]
+    fn test_labels() {
+        let source = "
+            start: jt 1, end     ; forward reference
+                   data start, end+1, end-1
+            end:   hlt
+        ";
+        assert_eq!(assemble(source).unwrap(), vec![1105, 1, 6, 0, 7, 5, 99]);
+
+        // Labels work on both sides of a relative base
+        let source = "
+                   out [rb+end]
+                   out [rb-end]
+                   out [rb-end+1]
+                   out [rb - end-1]
+            end:   hlt
+        ";
+        assert_eq!(assemble(source).unwrap(), vec![204, 8, 204, -8, 204, -7, 204, -9, 99]);
+        assert_eq!(assemble("out [rb-]").unwrap_err().line, 1);
+    }
+
+    #[test]
+    fn test_equal_to_eight() {
+        // Same as 3,9,8,9,10,9,4,9,99,-1,8
+        let source = "
+                in  [value]
+                eq  [value], [eight], [value]
+                out [value]
+                hlt
+        value:  data -1
+        eight:  data 8
+        ";
+        let program = assemble(source).unwrap();
+        assert_eq!(program, vec![3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8]);
+        assert_eq!(execute_with_result(&program, vec![8]), Ok(vec![1]));
+        assert_eq!(execute_with_result(&program, vec![7]), Ok(vec![0]));
+    }
+
+    #[test]
+    fn test_relative() {
+        let source = "
+                arb  counter
+        loop:   out  [rb]
+                add  [rb], 1, [rb]
+                lt   [rb], 3, [done]
+                jt   [done], loop
+                hlt
+        done:   data 0
+        counter: data 0
+        ";
+        let program = assemble(source).unwrap();
+        assert_eq!(execute_with_result(&program, vec![]), Ok(vec![0, 1, 2]));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(assemble("nop").unwrap_err().line, 1);
+        assert_eq!(assemble("hlt\nadd 1, 2").unwrap_err().line, 2);
+        assert_eq!(assemble("add 1, 2, 3").unwrap_err().line, 1);
+        assert_eq!(assemble("jt 1, nowhere").unwrap_err().line, 1);
+        assert_eq!(assemble("a: hlt\na: hlt").unwrap_err().line, 2);
+    }
+}
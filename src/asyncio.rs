@@ -0,0 +1,277 @@
+// Future based IO for IntMachine, next to the blocking IO trait.
+//
+// A machine waiting for input awaits AsyncIO::receive instead of blocking a thread, so any
+// number of machines can run as tasks on the single threaded Executor. Machines are connected
+// with channel(), dropping or shutting down the sending side ends the input of the receiver.
+
+use crate::intmachine::{IntMachine, IntcodeError, Memory, Message, PagedMemory, Status, Word, word_at};
+use crate::wide::WordType;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+
+pub type MessageFuture<'a, W = Word> = Pin<Box<dyn Future<Output = Message<W>> + 'a>>;
+
+pub trait AsyncIO<W = Word> {
+    fn send(&mut self, message: Message<W>);
+    fn receive(&mut self) -> MessageFuture<'_, W>;
+}
+
+// Runs the machine until it halts, awaiting input whenever the machine needs it
+pub async fn run_async<W: WordType>(machine: &mut IntMachine<W>, io: &mut dyn AsyncIO<W>) -> Result<(), IntcodeError> {
+    loop {
+        match machine.run()? {
+            Status::Output(data) => io.send(Message::Data(data)),
+            Status::NeedsInput => {
+                io.send(Message::RequestInput);
+                match io.receive().await {
+                    Message::Data(data) => machine.push_input(data),
+                    message => {
+                        let ip = machine.state().ip;
+                        let word = word_at(machine.memory(), ip);
+                        return Err(IntcodeError::UnexpectedInput { ip, word, message: message.to_word() });
+                    },
+                }
+            },
+            Status::Halted => {
+                io.send(Message::Shutdown);
+                return Ok(());
+            },
+        }
+    }
+}
+
+// Async counterpart of intmachine::execute
+pub async fn execute_async(initial: &Memory, io: &mut dyn AsyncIO) -> Result<PagedMemory, IntcodeError> {
+    let mut machine = IntMachine::new(initial);
+    run_async(&mut machine, io).await?;
+    return Ok(machine.memory().clone());
+}
+
+struct Channel {
+    queue: VecDeque<Word>,
+    waker: Option<Waker>,
+    closed: bool,
+}
+
+pub struct Sender {
+    channel: Rc<RefCell<Channel>>,
+}
+
+pub struct Receiver {
+    channel: Rc<RefCell<Channel>>,
+}
+
+// Unbounded single threaded channel of words
+pub fn channel() -> (Sender, Receiver) {
+    let channel = Rc::new(RefCell::new(Channel { queue: VecDeque::new(), waker: None, closed: false }));
+    return (Sender { channel: channel.clone() }, Receiver { channel });
+}
+
+impl Sender {
+    pub fn send(&self, data: Word) {
+        let mut channel = self.channel.borrow_mut();
+        channel.queue.push_back(data);
+        if let Some(waker) = channel.waker.take() {
+            waker.wake();
+        }
+    }
+
+    // The receiver gets Shutdown once the queued words are read
+    pub fn close(&self) {
+        let mut channel = self.channel.borrow_mut();
+        channel.closed = true;
+        if let Some(waker) = channel.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl Drop for Sender {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+impl Receiver {
+    pub fn recv(&self) -> Recv<'_> {
+        return Recv { receiver: self };
+    }
+
+    // Words that can be read without waiting
+    pub fn drain(&self) -> Vec<Word> {
+        return self.channel.borrow_mut().queue.drain(..).collect();
+    }
+}
+
+pub struct Recv<'a> {
+    receiver: &'a Receiver,
+}
+
+impl <'a> Future for Recv<'a> {
+    type Output = Message;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Message> {
+        let mut channel = self.receiver.channel.borrow_mut();
+        if let Some(data) = channel.queue.pop_front() {
+            return Poll::Ready(Message::Data(data));
+        }
+        if channel.closed {
+            return Poll::Ready(Message::Shutdown);
+        }
+        channel.waker = Some(cx.waker().clone());
+        return Poll::Pending;
+    }
+}
+
+// Reads input from one channel and writes output to another
+pub struct ChannelIO {
+    pub input: Receiver,
+    pub output: Sender,
+}
+
+impl AsyncIO for ChannelIO {
+    fn send(&mut self, message: Message) {
+        match message {
+            Message::Data(data) => self.output.send(data),
+            Message::Shutdown => self.output.close(),
+            Message::RequestInput => {},
+        }
+    }
+
+    fn receive(&mut self) -> MessageFuture<'_> {
+        return Box::pin(self.input.recv());
+    }
+}
+
+struct TaskWaker {
+    task: usize,
+    ready: Arc<Mutex<VecDeque<usize>>>,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.ready.lock().unwrap().push_back(self.task);
+    }
+}
+
+// Single threaded executor, tasks are polled in the order they are woken
+#[derive(Default)]
+pub struct Executor<'a> {
+    tasks: Vec<Option<Pin<Box<dyn Future<Output = ()> + 'a>>>>,
+    ready: Arc<Mutex<VecDeque<usize>>>,
+}
+
+impl <'a> Executor<'a> {
+    pub fn new() -> Executor<'a> {
+        return Executor::default();
+    }
+
+    pub fn spawn<F: Future<Output = ()> + 'a>(&mut self, future: F) {
+        self.ready.lock().unwrap().push_back(self.tasks.len());
+        self.tasks.push(Some(Box::pin(future)));
+    }
+
+    // Runs until no task can make progress, returns the number of tasks still waiting
+    pub fn run(&mut self) -> usize {
+        loop {
+            let task = match self.ready.lock().unwrap().pop_front() {
+                Some(task) => task,
+                None => break,
+            };
+            let future = match self.tasks[task].as_mut() {
+                Some(future) => future,
+                None => continue,
+            };
+            let waker = Waker::from(Arc::new(TaskWaker { task, ready: self.ready.clone() }));
+            if future.as_mut().poll(&mut Context::from_waker(&waker)).is_ready() {
+                self.tasks[task] = None;
+            }
+        }
+        return self.tasks.iter().filter(|t| t.is_some()).count();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::asyncio::{channel, execute_async, ChannelIO, Executor};
+    use crate::intmachine::IntcodeError;
+    use crate::assembler::assemble;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_chain() {
+        // 200 machines in a row, each adds one to every word passing through
+        let program = assemble("
+        loop:   in   [x]
+                add  [x], 1, [x]
+                out  [x]
+                jt   1, loop
+        x:      data 0
+        ").unwrap();
+        let (first, mut input) = channel();
+        let mut executor = Executor::new();
+        for _ in 0..200 {
+            let (output, next) = channel();
+            let mut io = ChannelIO { input, output };
+            let program = program.clone();
+            executor.spawn(async move {
+                let _ = execute_async(&program, &mut io).await;
+            });
+            input = next;
+        }
+        first.send(0);
+        first.send(5);
+        assert_eq!(executor.run(), 200);
+        assert_eq!(input.drain(), vec![200, 205]);
+
+        // Every machine fails when its input is shut down
+        drop(first);
+        assert_eq!(executor.run(), 0);
+    }
+
+    #[test]
+    fn test_halt() {
+        let (sender, receiver) = channel();
+        let (output, results) = channel();
+        let mut io = ChannelIO { input: receiver, output };
+        let errors = Rc::new(RefCell::new(vec![]));
+        let mut executor = Executor::new();
+        let program = vec![3,9,8,9,10,9,4,9,99,-1,8];
+        let task_errors = errors.clone();
+        executor.spawn(async move {
+            if let Err(error) = execute_async(&program, &mut io).await {
+                task_errors.borrow_mut().push(error);
+            }
+        });
+        assert_eq!(executor.run(), 1);
+        sender.send(8);
+        assert_eq!(executor.run(), 0);
+        assert_eq!(results.drain(), vec![1]);
+        assert_eq!(*errors.borrow(), vec![]);
+
+        // Shutdown instead of input
+        let (sender, receiver) = channel();
+        let (output, _results) = channel();
+        let mut io = ChannelIO { input: receiver, output };
+        let program = vec![3,9,8,9,10,9,4,9,99,-1,8];
+        let task_errors = errors.clone();
+        executor.spawn(async move {
+            if let Err(error) = execute_async(&program, &mut io).await {
+                task_errors.borrow_mut().push(error);
+            }
+        });
+        sender.close();
+        assert_eq!(executor.run(), 0);
+        let error = errors.borrow()[0].clone();
+        match error {
+            IntcodeError::UnexpectedInput { ip: 0, .. } => {},
+            _ => panic!("Unexpected error: {}", error),
+        }
+    }
+}
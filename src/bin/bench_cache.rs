@@ -0,0 +1,43 @@
+use std::env;
+use std::time::{Duration, Instant};
+use advent_of_code_2019::intmachine::{self, Memory, Word, IntcodeError};
+use advent_of_code_2019::icache::InstructionCache;
+
+type Execute<'a> = dyn FnMut(&Memory, Vec<Word>) -> Result<Vec<Word>, IntcodeError> + 'a;
+
+// Compares the plain interpreter with the decoded instruction cache on day19 style workloads,
+// one full program run per point of a size x size grid, all runs share one cache
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        println!("Usage: {} <program> [grid size]", args[0]);
+        return;
+    }
+    let program = intmachine::read_program(&args[1]);
+    let size: Word = match args.get(2) {
+        Some(size) => size.parse().unwrap(),
+        None => 50,
+    };
+
+    let (plain, plain_time) = run(&program, size, &mut intmachine::execute_with_result);
+    let mut cache = InstructionCache::new(program.len());
+    let (cached, cached_time) = run(&program, size, &mut |p, input| intmachine::execute_with_result_cached(p, input, &mut cache));
+    assert_eq!(plain, cached, "Cached run gave a different result");
+
+    println!("Runs: {}, sum of output: {}", size * size, plain);
+    println!("Interpreter: {:?}", plain_time);
+    println!("Cached:      {:?}", cached_time);
+    println!("Speedup:     {:.2}", plain_time.as_secs_f64() / cached_time.as_secs_f64());
+    println!("Cache hits:  {}, misses: {}", cache.hits(), cache.misses());
+}
+
+fn run(program: &Memory, size: Word, execute: &mut Execute) -> (Word, Duration) {
+    let start = Instant::now();
+    let mut sum = 0;
+    for y in 0..size {
+        for x in 0..size {
+            sum += execute(program, vec![x, y]).unwrap().iter().sum::<Word>();
+        }
+    }
+    return (sum, start.elapsed());
+}
@@ -1,4 +1,5 @@
 use std::env;
+use advent_of_code_2019::intmachine::{IntMachine, IsaLevel, Memory, Word};
 
 fn main() {
     let args: Vec<String> = env::args().collect();
@@ -12,7 +13,7 @@ fn main() {
 
 }
 
-fn find(result:i32, initial:Vec<i32>) -> i32 {
+fn find(result:Word, initial:Memory) -> Word {
     for noun in 0..255 {
         for verb in 0..173 {
             let r = run(noun, verb, initial.clone());
@@ -24,47 +25,24 @@ fn find(result:i32, initial:Vec<i32>) -> i32 {
     panic!("Not found");
 }
 
-fn run(noun:i32, verb:i32, initial:Vec<i32>) -> Vec<i32> {
+fn run(noun:Word, verb:Word, initial:Memory) -> Memory {
     let mut state = initial;
     state[1] = noun;
     state[2] = verb;
     return execute(state);
 }
 
-fn split_and_parse(s :&str) -> Vec<i32> {
+fn split_and_parse(s :&str) -> Memory {
     let split = s.trim().split(",");
-   return split.map(|x| x.parse::<i32>().unwrap()).collect();
+   return split.map(|x| x.parse::<Word>().unwrap()).collect();
 }
 
-fn execute(initial: Vec<i32>) -> Vec<i32> {
-    let mut ip = 0;
-    let mut state = initial;
-    loop {
-        let op_code = state[ip as usize];
-        if op_code == 99 {
-            break;
-        }
-        match op_code {
-            1 => {
-                // dst = op1 + op2
-                let op1 = state[ip + 1];
-                let op2 = state[ip + 2];
-                let dst = state[ip + 3];
-                state[dst as usize] = state[op1 as usize] + state[op2 as usize];
-                ip += 4;
-            }
-            2 => {
-                // dst = op1 * op2
-                let op1 = state[ip + 1];
-                let op2 = state[ip + 2];
-                let dst = state[ip + 3];
-                state[dst as usize] = state[op1 as usize]* state[op2 as usize];
-                ip += 4;
-            }
-            _ => panic!(),
-
-        }
-    }
+fn execute(initial: Memory) -> Memory {
+    let mut machine = IntMachine::new(&initial);
+    machine.set_isa(IsaLevel::Day02);
+    machine.run_to_end().unwrap();
+    let mut state = machine.memory().to_vec();
+    state.truncate(initial.len());
     return state;
 }
 
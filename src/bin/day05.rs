@@ -1,113 +1,8 @@
 use std::env;
+use advent_of_code_2019::intmachine::{IntMachine, IsaLevel, Memory, Word};
 
-use crate::Instruction::{Halt, Add, Multiply, Input, Output, JumpIfTrue, JumpIfFalse, LessThan, Equals};
-use crate::Parameter::{Imm, Pos};
-
-type Memory = Vec<i32>;
-type OutputData = Vec<i32>;
-type InputData = Vec<i32>;
-type IP = usize;
-
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
-enum Instruction {
-    Add {op1 :Parameter, op2: Parameter, dst: Parameter},
-    Multiply {op1 :Parameter, op2: Parameter, dst: Parameter},
-    Input {dst :Parameter},
-    Output {src :Parameter},
-    JumpIfTrue {cond: Parameter, target: Parameter},
-    JumpIfFalse {cond: Parameter, target: Parameter},
-    LessThan {op1 :Parameter, op2: Parameter, dst: Parameter},
-    Equals {op1 :Parameter, op2: Parameter, dst: Parameter},
-    Halt,
-}
-
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
-enum Parameter {
-    Pos(usize),
-    Imm(i32),
-}
-
-fn read_params(ip: IP, memory :&Memory, num: i32) -> Vec<Parameter> {
-    let mut params: Vec<Parameter> = vec![];
-    let mut param_code = memory[ip] / 100;
-    for i in 1..=num {
-        let val = memory[ip + i as usize];
-        let p = match param_code % 10 {
-            0 => Parameter::Pos(val as usize),
-            1 => Parameter::Imm(val),
-            _ => panic!("Invalid param: {}", memory[ip]/100),
-        };
-        param_code /= 10;
-        params.push(p)
-    }
-    return params;
-}
-
-fn decode_instruction(ip: IP, memory :&Memory) -> Instruction {
-    let op_code = memory[ip] % 100;
-    return match op_code {
-        1 => {
-            let params = read_params(ip, &memory, 3);
-            Add { op1: params[0], op2: params[1], dst: params[2] }
-        },
-        2 => {
-            let params = read_params(ip, &memory, 3);
-            Multiply { op1: params[0], op2: params[1], dst: params[2] }
-        },
-        3 => {
-            let params = read_params(ip, &memory, 1);
-            Input {dst: params[0]}
-        },
-        4 => {
-            let params = read_params(ip, &memory, 1);
-            Output {src: params[0]}
-        },
-        5 => {
-            let params = read_params(ip, &memory, 2);
-            JumpIfTrue {cond: params[0], target: params[1]}
-        },
-        6 => {
-            let params = read_params(ip, &memory, 2);
-            JumpIfFalse {cond: params[0], target: params[1]}
-        },
-        7 => {
-            let params = read_params(ip, &memory, 3);
-            LessThan {op1: params[0], op2: params[1], dst: params[2]}
-        },
-        8 => {
-            let params = read_params(ip, &memory, 3);
-            Equals {op1: params[0], op2: params[1], dst: params[2]}
-        },
-        99 => Halt,
-
-        _ => panic!("Unknown instruction: {:?}", memory[ip]),
-
-    };
-}
-
-fn write(memory :&mut Memory, val: i32, dst: Parameter) {
-    match dst {
-        Imm(_)   => panic!("Writing IMM"),
-        Pos(p) => {
-            //println!("Write: mem[{}] = {}", p, val);
-            memory[p] = val;
-        },
-    }
-}
-
-fn load(memory :&Memory, src: Parameter) -> i32 {
-    match src {
-        Imm(v) => {
-            //println!("Load const: {}", v);
-            return v;
-        }
-        Pos(p) => {
-            let val = memory[p];
-            //println!("Loaded: {} <- mem[{}]", val, p);
-            return val;
-        },
-    }
-}
+type OutputData = Vec<Word>;
+type InputData = Vec<Word>;
 
 fn main() {
     let args: Vec<String> = env::args().collect();
@@ -119,9 +14,9 @@ fn main() {
     println!("Result: {:?}", result);
 }
 
-fn split_and_parse(s :&str) -> Vec<i32> {
+fn split_and_parse(s :&str) -> Vec<Word> {
     let split = s.trim().split(",");
-   return split.map(|x| x.parse::<i32>().unwrap()).collect();
+   return split.map(|x| x.parse::<Word>().unwrap()).collect();
 }
 
 fn execute_with_result(initial: Memory, in_data: InputData) -> OutputData {
@@ -129,103 +24,22 @@ fn execute_with_result(initial: Memory, in_data: InputData) -> OutputData {
     return result;
 }
 
-fn execute(initial: Memory, in_data: Vec<i32>) -> (Memory, Vec<i32>)  {
-    //println!("Starting new execution");
-    let mut output = vec![];
-    let mut input = in_data;
-
-    let mut ip = 0;
-    let mut mem = initial;
-    loop {
-        //println!("Executing at IP:{}", ip);
-        let instruction = decode_instruction(ip, &mem);
-        match instruction {
-            Add {op1, op2, dst} => {
-                let v1 = load(&mem, op1);
-                let v2 = load(&mem, op2);
-                let res = v1 + v2;
-                write(&mut mem, res, dst);
-                ip += 4;
-            },
-            Multiply {op1, op2, dst} => {
-                let v1 = load(&mem, op1);
-                let v2 = load(&mem, op2);
-                let res = v1 * v2;
-                write(&mut mem, res, dst);
-                ip += 4;
-            },
-            Input {dst} => {
-                write(&mut mem, input.pop().unwrap(), dst);
-                ip += 2;
-            },
-            Output {src} => {
-                output.push(load(&mem, src));
-                ip += 2;
-            }
-            JumpIfTrue { cond, target } => {
-                let val = load(&mem, cond);
-                if val != 0 {
-                    let target = load(&mem, target);
-                    ip = target as usize;
-                } else {
-                    ip += 3
-                }
-            }
-            JumpIfFalse { cond, target } => {
-                let val = load(&mem, cond);
-                if val == 0 {
-                    let target = load(&mem, target);
-                    ip = target as usize;
-                } else {
-                    ip += 3
-                }
-            }
-            LessThan { op1, op2, dst } => {
-                let val1 = load(&mem, op1);
-                let val2 = load(&mem, op2);
-                let result;
-                if val1 < val2 {
-                    result = 1;
-                } else {
-                    result = 0;
-                }
-                write(&mut mem, result, dst);
-                ip += 4
-            }
-            Equals { op1, op2, dst } => {
-                let val1 = load(&mem, op1);
-                let val2 = load(&mem, op2);
-                let result;
-                if val1 == val2 {
-                    result = 1;
-                } else {
-                    result = 0;
-                }
-                write(&mut mem, result, dst);
-                ip += 4
-            }
-            Halt => {
-                println!("Halt!");
-                return (mem, output);
-            },
-            _ => panic!("Unknown instruction: {}", ip),
-        }
-    }
-}
-
-fn input() -> i32 {
-   return 1;
-}
-
-fn output(val :i32) {
-    println!("Output: {}", val)
+fn execute(initial: Memory, in_data: InputData) -> (Memory, OutputData)  {
+    let mut machine = IntMachine::new(&initial);
+    machine.set_isa(IsaLevel::Day05);
+    machine.push_inputs(&in_data);
+    let output = machine.run_to_end().unwrap();
+    let mut memory = machine.memory().to_vec();
+    memory.truncate(initial.len());
+    return (memory, output);
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{execute, split_and_parse, decode_instruction, execute_with_result};
-    use crate::Instruction::{Add, Multiply};
-    use crate::Parameter::{Imm, Pos};
+    use crate::{execute, split_and_parse, execute_with_result};
+    use advent_of_code_2019::intmachine::decode_instruction;
+    use advent_of_code_2019::intmachine::Instruction::{Add, Multiply};
+    use advent_of_code_2019::intmachine::Parameter::{Imm, Pos};
 
     #[test]
     fn test() {
@@ -234,10 +48,10 @@ mod tests {
 
     #[test]
     fn test_decode() {
-        assert_eq!(decode_instruction(0, &vec![1,0,0,0,99]),
-                   Add {op1: Pos(0), op2: Pos(0), dst: Pos(0) });
-        assert_eq!(decode_instruction(0, &vec![1002,4,3,4,33]),
-                   Multiply {op1: Pos(4), op2: Imm(3), dst: Pos(4) });
+        assert_eq!(decode_instruction(&0, &vec![1,0,0,0,99]),
+                   Ok(Add {op1: Pos(0), op2: Pos(0), dst: Pos(0) }));
+        assert_eq!(decode_instruction(&0, &vec![1002,4,3,4,33]),
+                   Ok(Multiply {op1: Pos(4), op2: Imm(3), dst: Pos(4) }));
     }
 
     #[test]
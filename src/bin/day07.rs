@@ -1,21 +1,7 @@
 use std::env;
+use advent_of_code_2019::intmachine::{IntMachine, IsaLevel, Memory, Status, Word};
 
-use crate::Instruction::{Halt, Add, Multiply, Input, Output, JumpIfTrue, JumpIfFalse, LessThan, Equals};
-use crate::Parameter::{Imm, Pos};
-use std::collections::VecDeque;
-use std::sync::mpsc::{Receiver, SyncSender};
-use std::sync::mpsc;
-use std::thread;
-
-type Memory = Vec<i32>;
-type OutputData = Vec<i32>;
-type InputData = VecDeque<i32>;
-type IP = usize;
-
-enum Message {
-    Shutdown,
-    Data(i32),
-}
+type OutputData = Vec<Word>;
 
 pub fn permutations(size: usize) -> Permutations {
     Permutations { idxs: (0..size).collect(), swaps: vec![0; size], i: 0 }
@@ -46,107 +32,6 @@ impl Iterator for Permutations {
     }
 }
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
-enum Instruction {
-    Add {op1 :Parameter, op2: Parameter, dst: Parameter},
-    Multiply {op1 :Parameter, op2: Parameter, dst: Parameter},
-    Input {dst :Parameter},
-    Output {src :Parameter},
-    JumpIfTrue {cond: Parameter, target: Parameter},
-    JumpIfFalse {cond: Parameter, target: Parameter},
-    LessThan {op1 :Parameter, op2: Parameter, dst: Parameter},
-    Equals {op1 :Parameter, op2: Parameter, dst: Parameter},
-    Halt,
-}
-
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
-enum Parameter {
-    Pos(usize),
-    Imm(i32),
-}
-
-fn read_params(ip: IP, memory :&Memory, num: i32) -> Vec<Parameter> {
-    let mut params: Vec<Parameter> = vec![];
-    let mut param_code = memory[ip] / 100;
-    for i in 1..=num {
-        let val = memory[ip + i as usize];
-        let p = match param_code % 10 {
-            0 => Parameter::Pos(val as usize),
-            1 => Parameter::Imm(val),
-            _ => panic!("Invalid param: {}", memory[ip]/100),
-        };
-        param_code /= 10;
-        params.push(p)
-    }
-    return params;
-}
-
-fn decode_instruction(ip: IP, memory :&Memory) -> Instruction {
-    let op_code = memory[ip] % 100;
-    return match op_code {
-        1 => {
-            let params = read_params(ip, &memory, 3);
-            Add { op1: params[0], op2: params[1], dst: params[2] }
-        },
-        2 => {
-            let params = read_params(ip, &memory, 3);
-            Multiply { op1: params[0], op2: params[1], dst: params[2] }
-        },
-        3 => {
-            let params = read_params(ip, &memory, 1);
-            Input {dst: params[0]}
-        },
-        4 => {
-            let params = read_params(ip, &memory, 1);
-            Output {src: params[0]}
-        },
-        5 => {
-            let params = read_params(ip, &memory, 2);
-            JumpIfTrue {cond: params[0], target: params[1]}
-        },
-        6 => {
-            let params = read_params(ip, &memory, 2);
-            JumpIfFalse {cond: params[0], target: params[1]}
-        },
-        7 => {
-            let params = read_params(ip, &memory, 3);
-            LessThan {op1: params[0], op2: params[1], dst: params[2]}
-        },
-        8 => {
-            let params = read_params(ip, &memory, 3);
-            Equals {op1: params[0], op2: params[1], dst: params[2]}
-        },
-        99 => Halt,
-
-        _ => panic!("Unknown instruction: {:?}", memory[ip]),
-
-    };
-}
-
-fn write(memory :&mut Memory, val: i32, dst: Parameter) {
-    match dst {
-        Imm(_)   => panic!("Writing IMM"),
-        Pos(p) => {
-            //println!("Write: mem[{}] = {}", p, val);
-            memory[p] = val;
-        },
-    }
-}
-
-fn load(memory :&Memory, src: Parameter) -> i32 {
-    match src {
-        Imm(v) => {
-            //println!("Load const: {}", v);
-            return v;
-        }
-        Pos(p) => {
-            let val = memory[p];
-            //println!("Loaded: {} <- mem[{}]", val, p);
-            return val;
-        },
-    }
-}
-
 fn main() {
     let args: Vec<String> = env::args().collect();
     let filename = &args[1];
@@ -158,79 +43,47 @@ fn main() {
     println!("Result: {:?}", result);
 }
 
-fn split_and_parse(s :&str) -> Vec<i32> {
+fn split_and_parse(s :&str) -> Vec<Word> {
     let split = s.trim().split(",");
-   return split.map(|x| x.parse::<i32>().unwrap()).collect();
+   return split.map(|x| x.parse::<Word>().unwrap()).collect();
 }
 
-fn execute_with_result(initial: Memory, in_data: Vec<i32>) -> OutputData {
-    let (input, pin): (SyncSender<Message>, Receiver<Message>) = mpsc::sync_channel(0);
-    let (pout, output): (SyncSender<Message>, Receiver<Message>) = mpsc::sync_channel(0);
-
-    let child = thread::spawn(move || {
-        execute(initial, pin, pout);
-    });
-
-    for data in in_data {
-        input.send(Message::Data(data));
-    }
-
-    let mut result = vec![];
-    loop {
-        match output.recv().unwrap() {
-            Message::Data(data) => result.push(data),
-            Message::Shutdown => break,
-        }
-    }
-    child.join();
-    return result;
+fn amplifier(initial: &Memory, setting: Word) -> IntMachine {
+    let mut machine = IntMachine::new(initial);
+    machine.set_isa(IsaLevel::Day05);
+    machine.push_input(setting);
+    return machine;
 }
 
-fn execute_feedback(initial: Memory, setting: Vec<i32>) -> i32 {
-    let (input_writer, input_reader): (SyncSender<Message>, Receiver<Message>) = mpsc::sync_channel(1);
-
-    let mut step_reader = input_reader;
-    let mut step_input_writer = input_writer.clone();
-
-    let mut children = Vec::new();
-    for s in setting {
-        let (s_out_writer, next_in_reader) : (SyncSender<Message>, Receiver<Message>) = mpsc::sync_channel(1);
-        // Send setting
-        step_input_writer.send(Message::Data(s));
-        let child_writer = s_out_writer.clone();
-        let mem = initial.clone();
-        let child = thread::spawn(move || {
-            execute(mem, step_reader, child_writer);
-        });
-        children.push(child);
-        step_reader = next_in_reader;
-        step_input_writer = s_out_writer;
-    }
-    let output = step_reader;
+fn execute_with_result(initial: Memory, in_data: Vec<Word>) -> OutputData {
+    let mut machine = IntMachine::new(&initial);
+    machine.set_isa(IsaLevel::Day05);
+    machine.push_inputs(&in_data);
+    return machine.run_to_end().unwrap();
+}
 
-    // Init
-    input_writer.send(Message::Data(0));
+// Passes the signal around the amplifiers until the last one halts
+fn execute_feedback(initial: Memory, setting: Vec<Word>) -> Word {
+    let mut amplifiers: Vec<IntMachine> = setting.iter().map(|s| amplifier(&initial, *s)).collect();
 
-    let mut max = i32::min_value();
+    let mut max = Word::min_value();
+    let mut signal = 0;
     loop {
-        match output.recv().unwrap() {
-            Message::Data(data) => {
-                if data > max {
-                    max = data;
-                }
-                input_writer.send(Message::Data(data));
-            },
-            Message::Shutdown => break,
+        for amplifier in amplifiers.iter_mut() {
+            amplifier.push_input(signal);
+            signal = match amplifier.run().unwrap() {
+                Status::Output(data) => data,
+                Status::Halted => return max,
+                Status::NeedsInput => panic!("Amplifier did not produce a signal"),
+            };
+        }
+        if signal > max {
+            max = signal;
         }
     }
-    for child in children {
-        child.join();
-    }
-
-    return max;
 }
 
-fn execute_phaser(initial: Memory, setting: Vec<i32>) -> i32 {
+fn execute_phaser(initial: Memory, setting: Vec<Word>) -> Word {
     let mut result = 0;
     for setting in setting {
         let output = execute_with_result(initial.clone(), vec![setting, result]);
@@ -240,12 +93,12 @@ fn execute_phaser(initial: Memory, setting: Vec<i32>) -> i32 {
     return result;
 }
 
-fn find_max_phaser(initial: Memory) -> i32 {
+fn find_max_phaser(initial: Memory) -> Word {
     let code = &[0,1,2,3,4];
     let mut max = -9999999;
 
     for perm in permutations(5) {
-        let settings :Vec<i32> = vec![
+        let settings :Vec<Word> = vec![
             code[perm[0]],
             code[perm[1]],
             code[perm[2]],
@@ -263,12 +116,12 @@ fn find_max_phaser(initial: Memory) -> i32 {
     return max;
 }
 
-fn find_max_feedback(initial: Memory) -> i32 {
+fn find_max_feedback(initial: Memory) -> Word {
     let code = &[5,6,7,8,9];
     let mut max = -9999999;
 
     for perm in permutations(5) {
-        let settings :Vec<i32> = vec![
+        let settings :Vec<Word> = vec![
             code[perm[0]],
             code[perm[1]],
             code[perm[2]],
@@ -286,97 +139,12 @@ fn find_max_feedback(initial: Memory) -> i32 {
     return max;
 }
 
-fn execute(initial: Memory, stdin: Receiver<Message>, stdout: SyncSender<Message>) -> (Memory)  {
-    let mut ip = 0;
-    let mut mem = initial;
-    loop {
-        //println!("Executing at IP:{}", ip);
-        let instruction = decode_instruction(ip, &mem);
-//        println!("Executing: {:?}", instruction);
-        match instruction {
-            Add {op1, op2, dst} => {
-                let v1 = load(&mem, op1);
-                let v2 = load(&mem, op2);
-                let res = v1 + v2;
-                write(&mut mem, res, dst);
-                ip += 4;
-            },
-            Multiply {op1, op2, dst} => {
-                let v1 = load(&mem, op1);
-                let v2 = load(&mem, op2);
-                let res = v1 * v2;
-                write(&mut mem, res, dst);
-                ip += 4;
-            },
-            Input {dst} => {
-                let val = match stdin.recv() {
-                    Ok(Message::Data(data)) => data,
-                    x => panic!(x),
-                };
-                write(&mut mem, val, dst);
-                ip += 2;
-            },
-            Output {src} => {
-                let val = load(&mem, src);
-                stdout.send(Message::Data(val));
-                ip += 2;
-            }
-            JumpIfTrue { cond, target } => {
-                let val = load(&mem, cond);
-                if val != 0 {
-                    let target = load(&mem, target);
-                    ip = target as usize;
-                } else {
-                    ip += 3
-                }
-            }
-            JumpIfFalse { cond, target } => {
-                let val = load(&mem, cond);
-                if val == 0 {
-                    let target = load(&mem, target);
-                    ip = target as usize;
-                } else {
-                    ip += 3
-                }
-            }
-            LessThan { op1, op2, dst } => {
-                let val1 = load(&mem, op1);
-                let val2 = load(&mem, op2);
-                let result;
-                if val1 < val2 {
-                    result = 1;
-                } else {
-                    result = 0;
-                }
-                write(&mut mem, result, dst);
-                ip += 4
-            }
-            Equals { op1, op2, dst } => {
-                let val1 = load(&mem, op1);
-                let val2 = load(&mem, op2);
-                let result;
-                if val1 == val2 {
-                    result = 1;
-                } else {
-                    result = 0;
-                }
-                write(&mut mem, result, dst);
-                ip += 4
-            }
-            Halt => {
-                stdout.send(Message::Shutdown);
-            //    println!("Halt!");
-                return mem;
-            },
-        }
-    }
-}
-
 #[cfg(test)]
 mod tests {
-    use crate::{split_and_parse, decode_instruction, execute_with_result, find_max_phaser, find_max_feedback};
-    use crate::Instruction::{Add, Multiply};
-    use crate::Parameter::{Imm, Pos};
+    use crate::{split_and_parse, execute_with_result, find_max_phaser, find_max_feedback};
+    use advent_of_code_2019::intmachine::decode_instruction;
+    use advent_of_code_2019::intmachine::Instruction::{Add, Multiply};
+    use advent_of_code_2019::intmachine::Parameter::{Imm, Pos};
 
     #[test]
     fn test() {
@@ -385,10 +153,10 @@ mod tests {
 
     #[test]
     fn test_decode() {
-        assert_eq!(decode_instruction(0, &vec![1,0,0,0,99]),
-                   Add {op1: Pos(0), op2: Pos(0), dst: Pos(0) });
-        assert_eq!(decode_instruction(0, &vec![1002,4,3,4,33]),
-                   Multiply {op1: Pos(4), op2: Imm(3), dst: Pos(4) });
+        assert_eq!(decode_instruction(&0, &vec![1,0,0,0,99]),
+                   Ok(Add {op1: Pos(0), op2: Pos(0), dst: Pos(0) }));
+        assert_eq!(decode_instruction(&0, &vec![1002,4,3,4,33]),
+                   Ok(Multiply {op1: Pos(4), op2: Imm(3), dst: Pos(4) }));
     }
 
     #[test]
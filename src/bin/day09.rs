@@ -1,203 +1,7 @@
 use std::env;
+use advent_of_code_2019::intmachine::{IntMachine, IsaLevel, Memory, Word};
 
-use crate::Instruction::{Halt, Add, Multiply, Input, Output, JumpIfTrue, JumpIfFalse, LessThan, Equals, AdjustRelativeBase};
-use crate::Parameter::{Imm, Pos, Rel};
-use std::collections::VecDeque;
-use std::sync::mpsc::{Receiver, SyncSender, Sender};
-use std::sync::mpsc;
-use std::thread;
-use std::io::{Write, stdout};
-use std::thread::sleep;
-use std::time::Duration;
-
-type Word = i64;
-type Memory = Vec<Word>;
 type OutputData = Vec<Word>;
-type InputData = VecDeque<Word>;
-
-enum Message {
-    Shutdown,
-    Data(Word),
-}
-
-pub fn permutations(size: usize) -> Permutations {
-    Permutations { idxs: (0..size).collect(), swaps: vec![0; size], i: 0 }
-}
-
-pub struct Permutations {
-    idxs: Vec<usize>,
-    swaps: Vec<usize>,
-    i: usize,
-}
-
-impl Iterator for Permutations {
-    type Item = Vec<usize>;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.i > 0 {
-            loop {
-                if self.i >= self.swaps.len() { return None; }
-                if self.swaps[self.i] < self.i { break; }
-                self.swaps[self.i] = 0;
-                self.i += 1;
-            }
-            self.idxs.swap(self.i, (self.i & 1) * self.swaps[self.i]);
-            self.swaps[self.i] += 1;
-        }
-        self.i = 1;
-        Some(self.idxs.clone())
-    }
-}
-
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
-struct ProcessorState {
-    ip :Word,
-    relative_base: Word,
-}
-
-struct IO {
-    stdin: Receiver<Message>,
-    stdout: SyncSender<Message>,
-}
-
-struct IntMachine {
-    memory: Memory,
-    state: ProcessorState,
-    io: IO,
-}
-
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
-enum Instruction {
-    Add {op1 :Parameter, op2: Parameter, dst: Parameter},
-    Multiply {op1 :Parameter, op2: Parameter, dst: Parameter},
-    Input {dst :Parameter},
-    Output {src :Parameter},
-    JumpIfTrue {cond: Parameter, target: Parameter},
-    JumpIfFalse {cond: Parameter, target: Parameter},
-    LessThan {op1 :Parameter, op2: Parameter, dst: Parameter},
-    Equals {op1 :Parameter, op2: Parameter, dst: Parameter},
-    Halt,
-    AdjustRelativeBase {op :Parameter},
-}
-
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
-enum Parameter {
-    Pos(Word),
-    Imm(Word),
-    Rel(Word),
-}
-
-fn read_params(ip: &Word, memory :&Memory, num: Word) -> Vec<Parameter> {
-    let mut params: Vec<Parameter> = vec![];
-    let mut param_code = memory[*ip as usize] / 100;
-    for i in 1..=num {
-        let val = memory[(ip + i) as usize];
-        let p = match param_code % 10 {
-            0 => Parameter::Pos(val),
-            1 => Parameter::Imm(val),
-            2 => Parameter::Rel(val),
-            _ => panic!("Invalid param: {}", memory[*ip as usize]/100),
-        };
-        param_code /= 10;
-        params.push(p)
-    }
-    return params;
-}
-
-fn decode_instruction(ip: &Word, memory :&Memory) -> Instruction {
-    let op_code = memory[*ip as usize] % 100;
-    return match op_code {
-        1 => {
-            let params = read_params(ip, &memory, 3);
-            Add { op1: params[0], op2: params[1], dst: params[2] }
-        },
-        2 => {
-            let params = read_params(ip, &memory, 3);
-            Multiply { op1: params[0], op2: params[1], dst: params[2] }
-        },
-        3 => {
-            let params = read_params(ip, &memory, 1);
-            Input { dst: params[0] }
-        },
-        4 => {
-            let params = read_params(ip, &memory, 1);
-            Output { src: params[0] }
-        },
-        5 => {
-            let params = read_params(ip, &memory, 2);
-            JumpIfTrue { cond: params[0], target: params[1] }
-        },
-        6 => {
-            let params = read_params(ip, &memory, 2);
-            JumpIfFalse { cond: params[0], target: params[1] }
-        },
-        7 => {
-            let params = read_params(ip, &memory, 3);
-            LessThan { op1: params[0], op2: params[1], dst: params[2] }
-        },
-        8 => {
-            let params = read_params(ip, &memory, 3);
-            Equals { op1: params[0], op2: params[1], dst: params[2] }
-        },
-        9 => {
-            let params = read_params(ip, &memory, 1);
-            AdjustRelativeBase { op: params[0] }
-        },
-        99 => Halt,
-        _ => {
-            panic!("Unknown instruction: {:?}", memory[*ip as usize]);
-        },
-    };
-}
-
-fn write_raw(memory :&mut Memory, address: &Word, val: Word) {
-    let a = *address as usize;
-    if a >= memory.len() {
-        println!("Out of memory write");
-        panic!("Writing out of memory");
-    }
-    memory[a] = val;
-}
-
-fn write(mut memory :&mut Memory, state: &ProcessorState, val: Word, dst: Parameter) {
-    match dst {
-        Imm(_)   => panic!("Writing IMM"),
-        Pos(p) => {
-            write_raw(&mut memory, &p, val);
-        },
-        Rel(p) => {
-            let address = state.relative_base + p;
-            write_raw(&mut memory, &address, val);
-        }
-    }
-}
-
-fn load_raw(memory: &Memory, address :&Word) -> Word {
-    let a = *address as usize;
-    if a >= memory.len() {
-        println!("Out of memory load");
-        panic!("Loading out of memory");
-    }
-    let val = memory[a];
-    //println!("Loaded: {} <- mem[{}]", val, p);
-    return val;
-}
-
-fn load(memory :&Memory, state: &ProcessorState, src: Parameter) -> Word {
-    match src {
-        Imm(v) => {
-            //println!("Load const: {}", v);
-            return v;
-        }
-        Pos(p) => {
-            return load_raw(memory, &p);
-        },
-        Rel(offset) => {
-            let address = state.relative_base + offset;
-            return load_raw(&memory, &address);
-        }
-    }
-}
 
 fn main() {
     let args: Vec<String> = env::args().collect();
@@ -216,150 +20,18 @@ fn split_and_parse(s :&str) -> Vec<Word> {
 }
 
 fn execute_with_result(initial: Memory, in_data: Vec<Word>) -> OutputData {
-    let (input, pin): (SyncSender<Message>, Receiver<Message>) = mpsc::sync_channel(0);
-    let (pout, output): (SyncSender<Message>, Receiver<Message>) = mpsc::sync_channel(0);
-
-    let child = thread::spawn(move || {
-        execute(&initial.clone(), pin, pout);
-    });
-
-    for data in in_data {
-        input.send(Message::Data(data));
-    }
-
-    let mut result = vec![];
-    loop {
-        match output.recv() {
-            Ok(message) => {
-                match message {
-                    Message::Data(data) => result.push(data),
-                    Message::Shutdown => break,
-                }
-            }
-            Err(error) => {
-                panic!("Error: {:?}", error.to_string());
-            }
-        }
-    }
-    child.join();
-    return result;
-}
-
-
-fn execute(initial: &Memory, stdin: Receiver<Message>, stdout: SyncSender<Message>) -> (Memory) {
-
-    const LEN :i64 = 640 * 1024 * 1024; // Should be enough..
-    let mut mem = vec![0; LEN as usize];
-    for i in 0..initial.len() {
-        mem[i] = initial[i];
-    }
-    let mut io = IO { stdin, stdout };
-    let mut state = ProcessorState { ip: 0, relative_base: 0 };
-
-    loop {
-        if execute_step(&mut mem, &mut state, &mut io) {
-            break;
-        }
-    }
-    return mem;
-
-}
-fn execute_step(mut mem: &mut Memory, mut state: &mut ProcessorState, io: &mut IO) -> bool{
-
-    let instruction = decode_instruction(&state.ip, &mem);
-    println!("Executing: {:?} {:?}", state, instruction);
-    stdout().flush();
-//    sleep(Duration::from_millis(100));
-    match instruction {
-        Add {op1, op2, dst} => {
-            let v1 = load(&mem, &state, op1);
-            let v2 = load(&mem, &state, op2);
-            let res = v1 + v2;
-            write(&mut mem, &state, res, dst);
-            state.ip += 4;
-        },
-        Multiply {op1, op2, dst} => {
-            let v1 = load(&mem, &state, op1);
-            let v2 = load(&mem, &state, op2);
-            let res = v1 * v2;
-            write(&mut mem, &state, res, dst);
-            state.ip += 4;
-        },
-        Input {dst} => {
-            let val = match io.stdin.recv() {
-                Ok(Message::Data(data)) => data,
-                x => panic!(x),
-            };
-            write(&mut mem, &state, val, dst);
-            state.ip += 2;
-        },
-        Output {src} => {
-            let val = load(&mem, &state, src);
-            io.stdout.send(Message::Data(val));
-            state.ip += 2;
-        }
-        JumpIfTrue { cond, target } => {
-            let val = load(&mem, &state, cond);
-            if val != 0 {
-                let target = load(&mem, &state, target);
-                state.ip = target;
-            } else {
-                state.ip += 3
-            }
-        }
-        JumpIfFalse { cond, target } => {
-            let val = load(&mem, &state, cond);
-            if val == 0 {
-                let target = load(&mem, &state, target);
-                state.ip = target;
-            } else {
-                state.ip += 3
-            }
-        }
-        LessThan { op1, op2, dst } => {
-            let val1 = load(&mem, &state, op1);
-            let val2 = load(&mem, &state, op2);
-            let result;
-            if val1 < val2 {
-                result = 1;
-            } else {
-                result = 0;
-            }
-            write(&mut mem, &state, result, dst);
-            state.ip += 4
-        }
-        Equals { op1, op2, dst } => {
-            let val1 = load(&mem, &state, op1);
-            let val2 = load(&mem, &state, op2);
-            let result;
-            if val1 == val2 {
-                result = 1;
-            } else {
-                result = 0;
-            }
-            write(&mut mem, &state, result, dst);
-            state.ip += 4
-        }
-        Halt => {
-            io.stdout.send(Message::Shutdown);
-            return true;
-            //    println!("Halt!");
-            stdout().flush();
-        },
-        AdjustRelativeBase { op } => {
-            //println!("Adjusting!");
-            state.relative_base += load(&mut mem, &state, op);
-            state.ip += 2;
-        }
-    }
-    return false;
+    let mut machine = IntMachine::new(&initial);
+    machine.set_isa(IsaLevel::Day09);
+    machine.push_inputs(&in_data);
+    return machine.run_to_end().unwrap();
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{split_and_parse, decode_instruction, execute_with_result};
-    use crate::Instruction::{Add, Multiply};
-    use crate::Parameter::{Imm, Pos};
+    use crate::{split_and_parse, execute_with_result};
+    use advent_of_code_2019::intmachine::decode_instruction;
+    use advent_of_code_2019::intmachine::Instruction::{Add, Multiply};
+    use advent_of_code_2019::intmachine::Parameter::{Imm, Pos};
 
     #[test]
     fn test() {
@@ -369,9 +41,9 @@ mod tests {
     #[test]
     fn test_decode() {
         assert_eq!(decode_instruction(&0, &vec![1,0,0,0,99]),
-                   Add {op1: Pos(0), op2: Pos(0), dst: Pos(0) });
+                   Ok(Add {op1: Pos(0), op2: Pos(0), dst: Pos(0) }));
         assert_eq!(decode_instruction(&0, &vec![1002,4,3,4,33]),
-                   Multiply {op1: Pos(4), op2: Imm(3), dst: Pos(4) });
+                   Ok(Multiply {op1: Pos(4), op2: Imm(3), dst: Pos(4) }));
     }
 
     #[test]
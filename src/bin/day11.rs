@@ -1,11 +1,9 @@
-use std::{env, thread};
+use std::env;
 use advent_of_code_2019::intmachine;
 use std::cell::RefCell;
 use std::rc::Rc;
 use std::collections::HashMap;
-use std::sync::mpsc::{SyncSender, Receiver};
-use advent_of_code_2019::intmachine::Message;
-use std::sync::mpsc;
+use advent_of_code_2019::intmachine::{IntMachine, Status};
 use std::borrow::Borrow;
 use std::collections::hash_map::RandomState;
 
@@ -181,39 +179,22 @@ fn main() {
     let hull = Rc::new(Hull::new());
     let mut bot = PaintBot::new(&hull);
 
-    let (input, pin): (SyncSender<Message>, Receiver<Message>) = mpsc::sync_channel(0);
-    let (pout, output): (SyncSender<Message>, Receiver<Message>) = mpsc::sync_channel(0);
-
-    let child = thread::spawn(move || {
-        intmachine::execute(&program, pin, pout);
-    });
-
-
+    let mut machine = IntMachine::new(&program);
     loop {
-        match output.recv() {
-            Ok(message) => {
-                match message {
-                    Message::Data(data) =>  {
-                        bot.execute(&data);
-                    }
-
-                    Message::Shutdown => break,
-                    Message::RequestInput => {
-                        let scan = match bot.scan() {
-                            Color::Black => 0,
-                            Color::White => 1,
-                        };
-                        input.send(Message::Data(scan));
-
-                    }
-                }
+        match machine.run().unwrap() {
+            Status::Output(data) => {
+                bot.execute(&data);
             }
-            Err(error) => {
-                panic!("Error: {:?}", error.to_string());
+            Status::NeedsInput => {
+                let scan = match bot.scan() {
+                    Color::Black => 0,
+                    Color::White => 1,
+                };
+                machine.push_input(scan);
             }
+            Status::Halted => break,
         }
     }
-    child.join();
 
     hull.print();
 }
@@ -1,11 +1,9 @@
-use std::{env, thread};
+use std::env;
 use advent_of_code_2019::intmachine;
 use std::cell::RefCell;
 use std::rc::Rc;
 use std::collections::HashMap;
-use std::sync::mpsc::{SyncSender, Receiver};
-use advent_of_code_2019::intmachine::{Message, Word};
-use std::sync::mpsc;
+use advent_of_code_2019::intmachine::{IntMachine, Status, Word};
 use std::borrow::Borrow;
 use std::collections::hash_map::RandomState;
 use crate::Tile::{Empty, Wall, Block, Paddle, Ball};
@@ -168,35 +166,21 @@ fn main() {
 
     let mut arcade = ArcadeCabinet::new();
 
-    let (input, pin): (SyncSender<Message>, Receiver<Message>) = mpsc::sync_channel(0);
-    let (pout, output): (SyncSender<Message>, Receiver<Message>) = mpsc::sync_channel(0);
-
-    let child = thread::spawn(move || {
-        intmachine::execute(&program, pin, pout);
-    });
+    let mut machine = IntMachine::new(&program);
 
 
     loop {
-        match output.recv() {
-            Ok(message) => {
-                match message {
-                    Message::Data(data) =>  {
-                        arcade.output(data);
-
-                    }
-
-                    Message::Shutdown => break,
-                    Message::RequestInput => {
-               //         input.send(Message::Data(calc_input(&arcade.screen)));
-                    }
-                }
-            }
-            Err(error) => {
-                panic!("Error: {:?}", error.to_string());
+        match machine.run().unwrap() {
+            Status::Output(data) =>  {
+                arcade.output(data);
+
             }
+
+            Status::Halted => break,
+            // The joystick follows the ball, run() would ask again forever without input
+            Status::NeedsInput => machine.push_input(calc_input(&arcade.screen)),
         }
     }
-    child.join();
 
     let result = arcade.score;
     arcade.screen.print();
@@ -1,11 +1,9 @@
-use std::{env, thread};
+use std::env;
 use advent_of_code_2019::intmachine;
 use std::cell::RefCell;
 use std::rc::Rc;
-use std::collections::HashMap;
-use std::sync::mpsc::{SyncSender, Receiver};
-use advent_of_code_2019::intmachine::{Message, Word};
-use std::sync::mpsc;
+use std::collections::{HashMap, HashSet, VecDeque};
+use advent_of_code_2019::intmachine::{IntMachine, Status, Word};
 use std::borrow::Borrow;
 use std::collections::hash_map::RandomState;
 use std::thread::sleep;
@@ -184,6 +182,57 @@ impl Controller {
 
 }
 
+fn moved(pos: (Word, Word), dir: &Direction) -> (Word, Word) {
+    let (x, y) = pos;
+    return match dir {
+        North => (x, y - 1),
+        South => (x, y + 1),
+        West => (x - 1, y),
+        East => (x + 1, y),
+    }
+}
+
+// Breadth first search, forking the droid at every open tile. Returns the oxygen system
+// with its distance, position and the droid standing on it, and the distance to the furthest tile.
+fn explore(droid: &IntMachine, start: (Word, Word), controller: &mut Controller) -> (Option<(i64, (Word, Word), IntMachine)>, i64) {
+    let mut seen: HashSet<(Word, Word)> = HashSet::new();
+    let mut queue: VecDeque<(IntMachine, (Word, Word), i64)> = VecDeque::new();
+    let mut oxygen = None;
+    let mut max = 0;
+    seen.insert(start);
+    queue.push_back((droid.clone(), start, 0));
+
+    while let Some((droid, pos, dist)) = queue.pop_front() {
+        max = i64::max(max, dist);
+        for r in 1..5 {
+            let dir = Direction::from(r);
+            let next = moved(pos, &dir);
+            if !seen.insert(next) {
+                continue;
+            }
+            let mut fork = droid.clone();
+            fork.push_input(r);
+            let tile = match fork.run().unwrap() {
+                Status::Output(0) => Wall,
+                Status::Output(1) => Empty,
+                Status::Output(2) => OxygenSystem,
+                status => panic!("Unexpected status: {:?}", status),
+            };
+            if controller.map[(next.1 * controller.x_size + next.0) as usize] == Unknown {
+                controller.update_tile(&next.0, &next.1, tile.clone());
+            }
+            if tile == Wall {
+                continue;
+            }
+            if tile == OxygenSystem && oxygen.is_none() {
+                oxygen = Some((dist + 1, next, fork.clone()));
+            }
+            queue.push_back((fork, next, dist + 1));
+        }
+    }
+    return (oxygen, max);
+}
+
 fn main() {
     /*
     let args: Vec<String> = env::args().collect();
@@ -191,55 +240,20 @@ fn main() {
     */
 
     let filename = "data/day15/input.txt";
-    let mut program = intmachine::read_program(filename);
+    let program = intmachine::read_program(filename);
 
     let mut controller:Controller = Controller::new(180, 180 );
+    let start = controller.robot_pos;
 
-    let (input, pin): (SyncSender<Message>, Receiver<Message>) = mpsc::sync_channel(0);
-    let (pout, output): (SyncSender<Message>, Receiver<Message>) = mpsc::sync_channel(0);
-
-    let child = thread::spawn(move || {
-        intmachine::execute(&program, pin, pout);
-    });
-
-    let mut rng = rand::thread_rng();
-
-    let mut count = 0;
-    loop {
-        if count % 10000 == 0 {
-            println!("Max is: {}", controller.get_max_dist());
-            //print!("{}[2J", 27 as char);
-            //controller.print();
-        }
-        count += 1;
-
-        match output.recv() {
-            Ok(message) => {
-                match message {
-                    Message::Data(data) =>  {
-                        controller.update(data);
-                        controller.state = Input;
-                    }
-
-                    Message::Shutdown => break,
-                    Message::RequestInput => {
-                        let r = rng.gen_range(1, 5);
-//                        println!("Random walk: {}", &r);
-                        input.send(Message::Data(r));
-                        controller.state = WaitResponse(Direction::from(r));
-                    }
-                }
-          }
-            Err(error) => {
-                panic!("Error: {:?}", error.to_string());
-            }
-        }
-    }
-    child.join();
-
+    let droid = IntMachine::new(&program);
+    let (oxygen, _) = explore(&droid, start, &mut controller);
+    let (dist, pos, droid) = oxygen.expect("No oxygen system found");
     controller.print();
-//    println!("Score: {}", result);
+    println!("Distance to oxygen system: {}", dist);
 
+    // Oxygen spreads one tile per minute, so the fill time is the furthest tile from the system
+    let (_, minutes) = explore(&droid, pos, &mut controller);
+    println!("Minutes to fill: {}", minutes);
 }
 
 #[cfg(test)]
@@ -1,11 +1,11 @@
-use std::{env, thread};
+use std::env;
 use advent_of_code_2019::intmachine;
 use std::cell::RefCell;
 use std::rc::Rc;
-use std::collections::{HashMap, VecDeque};
-use std::sync::mpsc::{SyncSender, Receiver};
-use advent_of_code_2019::intmachine::{Message, Word};
-use std::sync::mpsc;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use advent_of_code_2019::intmachine::Word;
+use advent_of_code_2019::ascii::AsciiMachine;
 use std::borrow::Borrow;
 use std::collections::hash_map::RandomState;
 use std::thread::sleep;
@@ -141,106 +141,179 @@ impl Controller {
 
 }
 
-fn main() {
-    /*
-    let args: Vec<String> = env::args().collect();
-    let filename = &args[1];
-    */
-
-    let filename = "data/day17/input.txt";
-    let mut program = intmachine::read_program(filename);
-    program[0] = 2;
-
-    let mut controller:Controller = Controller::new();
-
-    let (input, pin): (SyncSender<Message>, Receiver<Message>) = mpsc::sync_channel(0);
-    let (pout, output): (SyncSender<Message>, Receiver<Message>) = mpsc::sync_channel(0);
-
-    let child = thread::spawn(move || {
-        intmachine::execute(&program, pin, pout);
-    });
-
-    let mut rng = rand::thread_rng();
-
-    let mut count = 0;
-    let main = "A,A,C,B,C,B,C,B,C,A\n";
-    let a = "L,10,L,8,R,8,L,8,R,6\n";
-    let b = "R,6,R,6,L,8,L,10\n";
-    let c = "R,6,R,8,R,8\n";
-    /*
-    let main = "A\n";
-    let a = "L,2,2,2\n";
-    let b = "R,2\n";
-    let c = "R,2\n";
-    */
-
+// Longest routine the robot accepts, not counting the newline
+const MAX_ROUTINE: usize = 20;
+
+type Position = (i32, i32);
+
+// Scaffold tiles and the robot, position and direction (dx, dy), from the camera image
+fn read_image(image: &str) -> (HashSet<Position>, Position, Position) {
+    let mut scaffold = HashSet::new();
+    let mut robot = ((0, 0), (0, -1));
+    for (y, line) in image.lines().enumerate() {
+        for (x, c) in line.chars().enumerate() {
+            let position = (x as i32, y as i32);
+            let direction = match c {
+                '#' => {
+                    scaffold.insert(position);
+                    continue;
+                },
+                '^' => (0, -1),
+                'v' => (0, 1),
+                '<' => (-1, 0),
+                '>' => (1, 0),
+                _ => continue,
+            };
+            scaffold.insert(position);
+            robot = (position, direction);
+        }
+    }
+    return (scaffold, robot.0, robot.1);
+}
 
-    let mut d :VecDeque<char> = VecDeque::new();
-    d.extend(main.chars());
-    d.extend(a.chars());
-    d.extend(b.chars());
-    d.extend(c.chars());
-    d.extend(String::from("n\n").chars());
+// Follows the scaffold straight ahead as far as possible, turning only at its ends.
+// Each step is a turn and a distance, like "L,10"
+fn find_path(scaffold: &HashSet<Position>, start: Position, direction: Position) -> Vec<String> {
+    let mut path = vec![];
+    let (mut x, mut y) = start;
+    let (mut dx, mut dy) = direction;
     loop {
-        if count % 10000 == 0 {
-            //print!("{}[2J", 27 as char);
-            //controller.print();
+        let (left, right) = ((dy, -dx), (-dy, dx));
+        let turn = if scaffold.contains(&(x + left.0, y + left.1)) {
+            (dx, dy) = left;
+            "L"
+        } else if scaffold.contains(&(x + right.0, y + right.1)) {
+            (dx, dy) = right;
+            "R"
+        } else {
+            return path;
+        };
+        let mut distance = 0;
+        while scaffold.contains(&(x + dx, y + dy)) {
+            x += dx;
+            y += dy;
+            distance += 1;
         }
-        count += 1;
-
-        /*
-  L10,L8,R8,L8,R6 R6,R8,R8,R6,R6,L8,L10 R6,R8,R8,R6,R6,L8,L10  R6,R8,R8,R6,R6,L8,L10
-  A               B                     B                      B
-
-  R6,R8,R8,L10,L8,R8,L8,R6
-  C
-  */
-
-
-        match output.recv() {
-            Ok(message) => {
-                match message {
-                    Message::Data(data) =>  {
-                        if data > 256 {
-                            println!("Received: {}", data);
-                        }
-                        let c = char::from(data as u8);
-//                        controller.update(&c);
-                        print!("{}", c);
-                    }
-
-                    Message::Shutdown => {
-                        println!("Shutdown..");
-                        break;
-                    }
-                    Message::RequestInput => {
-//                        println!("d: {:?}",d);
-                        let mc = d.pop_front();
-
-                        let c :char = mc.unwrap();
-
-//                        println!("sending: {}", &c);
-                        input.send(Message::Data(c as Word));
-
-//                        println!("Random walk: {}", &r);
-//                        input.send(Message::Data(r));
-//                        controller.state = WaitResponse(Direction::from(r));
-                    }
-                }
-          }
-            Err(error) => {
-                panic!("Error: {:?}", error.to_string());
+        path.push(format!("{},{}", turn, distance));
+    }
+}
+
+// Splits the path into a main routine calling A, B and C, None if it does not fit
+fn compress(path: &[String]) -> Option<(String, Vec<String>)> {
+    let mut calls = vec![];
+    let mut functions = vec![];
+    if !fit(path, &mut functions, &mut calls) {
+        return None;
+    }
+    let main: Vec<String> = calls.iter().map(|f| ((b'A' + *f as u8) as char).to_string()).collect();
+    return Some((main.join(","), functions.iter().map(|f| f.join(",")).collect()));
+}
+
+fn fit<'a>(path: &'a [String], functions: &mut Vec<&'a [String]>, calls: &mut Vec<usize>) -> bool {
+    if path.is_empty() {
+        return true;
+    }
+    // Every call takes two characters, except the last one has no comma
+    if 2 * calls.len() + 1 > MAX_ROUTINE {
+        return false;
+    }
+    for f in 0..functions.len() {
+        if path.starts_with(functions[f]) {
+            calls.push(f);
+            if fit(&path[functions[f].len()..], functions, calls) {
+                return true;
             }
+            calls.pop();
+        }
+    }
+    if functions.len() == 3 {
+        return false;
+    }
+    for n in 1..=path.len() {
+        if path[..n].join(",").len() > MAX_ROUTINE {
+            break;
+        }
+        functions.push(&path[..n]);
+        calls.push(functions.len() - 1);
+        if fit(&path[n..], functions, calls) {
+            return true;
         }
+        calls.pop();
+        functions.pop();
     }
-    child.join();
+    return false;
+}
 
-    controller.print();
-//    println!("Score: {}", controller.align());
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let filename = args.get(1).map_or("data/day17/input.txt", |f| f.as_str());
+    let mut program = intmachine::read_program(filename);
 
+    let (image, _) = AsciiMachine::new(&program).read_all().unwrap();
+    print!("{}", image);
+    let (scaffold, start, direction) = read_image(&image);
+    let path = find_path(&scaffold, start, direction);
+    println!("Path: {}", path.join(","));
+    let (main, functions) = match compress(&path) {
+        Some(routines) => routines,
+        None => {
+            println!("The path can not be split into routines of at most {} characters", MAX_ROUTINE);
+            return;
+        },
+    };
+    println!("Main: {}", main);
+    for (name, function) in ["A", "B", "C"].iter().zip(functions.iter()) {
+        println!("{}: {}", name, function);
+    }
+
+    program[0] = 2;
+    let mut machine = AsciiMachine::new(&program);
+    let mut lines = vec![main.as_str()];
+    lines.extend(functions.iter().map(|f| f.as_str()));
+    // Functions that are not needed are still asked for
+    lines.resize(4, "L");
+    lines.push("n");
+    machine.send_lines(&lines);
+    let (_, values) = machine.read_all().unwrap();
+    match values.last() {
+        Some(dust) => println!("Dust collected: {}", dust),
+        None => println!("The robot did not finish"),
+    }
 }
 
 #[cfg(test)]
 mod tests {
-
-}
\ No newline at end of file
+    use crate::{compress, find_path, read_image};
+
+    #[test]
+    fn test_compress() {
+        let image = "\
+#######...#####
+#.....#...#...#
+#.....#...#...#
+......#...#...#
+......#...###.#
+......#.....#.#
+^########...#.#
+......#.#...#.#
+......#########
+........#...#..
+....#########..
+....#...#......
+....#...#......
+....#...#......
+....#####......
+";
+        let (scaffold, start, direction) = read_image(image);
+        let path = find_path(&scaffold, start, direction);
+        assert_eq!(path.join(","), "R,8,R,8,R,4,R,4,R,8,L,6,L,2,R,4,R,4,R,8,R,8,R,8,L,6,L,2");
+        let (main, functions) = compress(&path).unwrap();
+        assert!(main.len() <= 20 && functions.iter().all(|f| f.len() <= 20));
+        let expanded: Vec<&str> = main.split(',').map(|f| functions[(f.as_bytes()[0] - b'A') as usize].as_str()).collect();
+        assert_eq!(expanded.join(","), path.join(","));
+
+        // A function holds at most four of these steps and main calls at most ten functions
+        let path = vec!["L,10".to_string(); 41];
+        assert_eq!(compress(&path), None);
+    }
+}
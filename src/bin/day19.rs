@@ -4,7 +4,8 @@ use std::cell::RefCell;
 use std::rc::Rc;
 use std::collections::{HashMap, VecDeque};
 use std::sync::mpsc::{SyncSender, Receiver};
-use advent_of_code_2019::intmachine::{Message, Word, execute_with_result, Memory};
+use advent_of_code_2019::intmachine::{Message, Word, execute_with_result_cached, Memory};
+use advent_of_code_2019::icache::InstructionCache;
 use std::sync::mpsc;
 use std::borrow::Borrow;
 use std::collections::hash_map::RandomState;
@@ -95,6 +96,8 @@ fn main() {
 
     let filename = "data/day19/input.txt";
     let mut program = intmachine::read_program(filename);
+    // Every point is a new run of the same program, they all share the decoded instructions
+    let mut cache = InstructionCache::new(program.len());
 
     let mut rng = rand::thread_rng();
 
@@ -106,7 +109,7 @@ fn main() {
     let mut count = 0;
     for y in 0..y_size {
         for x in 0..x_size {
-            let result =  match is_tractor(&program, x, y)  {
+            let result =  match is_tractor(&program, &mut cache, x, y)  {
                 true => Tile::Pull,
                 false => Tile::NoPull,
             };
@@ -127,18 +130,18 @@ fn main() {
     */
     //find_box(&program, 1728, 100);
     for r in (1700..1713).step_by(1) {
-        let res = find_box(&program, r, 99);
+        let res = find_box(&program, &mut cache, r, 99);
         println!("Ordering ({}): {:?}", r, res);
     }
 }
 
 
-fn is_tractor(program: &Memory, x: Word, y: Word) -> bool {
-    let result = execute_with_result(program, vec![x,y]);
+fn is_tractor(program: &Memory, cache: &mut InstructionCache, x: Word, y: Word) -> bool {
+    let result = execute_with_result_cached(program, vec![x,y], cache).unwrap();
     return result[0] == 1;
 }
 
-fn find_box(program: &Memory, row: Word, box_size: Word) -> Ordering {
+fn find_box(program: &Memory, cache: &mut InstructionCache, row: Word, box_size: Word) -> Ordering {
     let x_max = 999999;
     // Find leftmost
     let mut x_start = 0;
@@ -146,14 +149,14 @@ fn find_box(program: &Memory, row: Word, box_size: Word) -> Ordering {
         if x_start >= x_max {
             panic!("Did not find beam on row: {}", row);
         }
-        if is_tractor(program, x_start, row) {
+        if is_tractor(program, cache, x_start, row) {
             break;
         }
         x_start += 1;
     }
     let mut x_last = x_start;
     loop {
-        if !is_tractor(program, x_last + 1, row) {
+        if !is_tractor(program, cache, x_last + 1, row) {
             break;
         }
         x_last += 1;
@@ -163,11 +166,11 @@ fn find_box(program: &Memory, row: Word, box_size: Word) -> Ordering {
         return Ordering::Less;
     }
 
-    if is_tractor(program, x_last - (box_size + 1), row + box_size + 1) {
+    if is_tractor(program, cache, x_last - (box_size + 1), row + box_size + 1) {
        return Ordering::Greater;
     }
 
-    if is_tractor(program, x_last - box_size, row + box_size) {
+    if is_tractor(program, cache, x_last - box_size, row + box_size) {
         let x = x_last - box_size;
         println!("Point: {} {}", x, row);
         println!("Result: {}", x * 10000 + row);
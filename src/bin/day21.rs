@@ -1,228 +1,159 @@
-use std::{env, thread};
+use std::env;
 use advent_of_code_2019::intmachine;
-use std::cell::RefCell;
-use std::rc::Rc;
-use std::collections::{HashMap, VecDeque};
-use std::sync::mpsc::{SyncSender, Receiver};
-use advent_of_code_2019::intmachine::{Message, Word};
-use std::sync::mpsc;
-use std::borrow::Borrow;
-use std::collections::hash_map::RandomState;
-use std::thread::sleep;
-use std::time::Duration;
-use rand::Rng;
-use crate::State::{Input, WaitResponse};
-use crate::Direction::{North, South, West, East};
-use crate::Tile::{Empty, Scaffold};
+use advent_of_code_2019::intmachine::{Memory, Word};
+use advent_of_code_2019::springscript::{self, hull_from_report, Mode, Outcome};
+use advent_of_code_2019::ascii::{AsciiMachine, Reply};
+use rand::SeedableRng;
+use rand::rngs::StdRng;
 
+// Jump when there is a hole in A, B or C and ground to land on at D
+const WALK: &str = "(!A | !B | !C) & D";
+// Same, but only when the droid can walk on from D (E) or jump again right away (H)
+const RUN: &str = "(!A | !B | !C) & D & (E | H)";
 
-#[derive(Clone, Eq, PartialEq, Debug)]
-enum Tile {
-    Empty,
-    Scaffold,
-}
-
-#[derive(Clone, Eq, PartialEq, Debug)]
-enum Direction {
-    North,
-    South,
-    West,
-    East,
-}
-
-impl Direction {
-    fn from(x: Word) -> Direction {
-        match x {
-            1 => North,
-            2 => South,
-            3 => West,
-            4 => East,
-            _ => panic!(),
-
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let filename = "data/day21/input.txt";
+    let program = intmachine::read_program(filename);
+
+    if args.get(1).map(|a| a.as_str()) == Some("--search") {
+        for mode in [Mode::Walk, Mode::Run].iter() {
+            match search_script(&program, *mode, true) {
+                Some((script, damage)) => {
+                    print!("{}", script);
+                    println!("Hull damage ({:?}): {}", mode, damage);
+                },
+                None => println!("No script found for {:?}", mode),
+            }
         }
+        return;
     }
-}
 
-
-#[derive(Clone, Eq, PartialEq, Debug)]
-enum State {
-    WaitResponse(Direction),
-    Input,
-}
-
-impl Tile {
-    fn repr(&self) -> char {
-        return match self {
-            Empty => '.',
-            Scaffold => '#',
+    let walk = args.get(1).map_or(WALK, |f| f.as_str());
+    let run = args.get(2).map_or(RUN, |f| f.as_str());
+    for (formula, mode) in [(walk, Mode::Walk), (run, Mode::Run)].iter() {
+        let script = match springscript::compile(formula, *mode) {
+            Ok(script) => script,
+            Err(error) => {
+                println!("{}: {}", formula, error);
+                continue;
+            },
+        };
+        print!("{}", script);
+        match run_script(&program, &script) {
+            Ok(damage) => println!("Hull damage ({:?}): {}", mode, damage),
+            Err(Failure::Rejected(text)) => {
+                print!("{}", text);
+                println!("Springscript was not accepted");
+            },
+            Err(Failure::Fell(report)) => {
+                print!("{}", report);
+                match hull_from_report(&report) {
+                    Some(hull) => match script.simulate(&hull) {
+                        Outcome::Fell { position, jumps } =>
+                            println!("Falls into the hole at {} of {}, jumped at {:?}", position, hull, jumps),
+                        Outcome::Survived { .. } => println!("Makes it across {} when simulated", hull),
+                    },
+                    None => println!("The droid fell into space"),
+                }
+            },
         }
     }
 }
 
-#[derive(Clone, Eq, PartialEq, Debug)]
-struct Robot {
-    x: i32,
-    y: i32,
-    direction: Direction,
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Failure {
+    // The droid fell, with the report it printed
+    Fell(String),
+    // The droid did not run the script, with what it printed
+    Rejected(String),
 }
 
-
-#[derive(Clone, Eq, PartialEq, Debug)]
-struct Controller {
-    x :i64,
-    y :i64,
-    map: Vec<Vec<Tile>>,
-    robot: Option<Robot>,
-//    state: State,
+// Runs the script on the springdroid, the hull damage if it made it across
+fn run_script(program: &Memory, script: &springscript::Program) -> Result<Word, Failure> {
+    let mut machine = AsciiMachine::new(program);
+    let lines = script.lines();
+    machine.send_lines(&lines.iter().map(|l| l.as_str()).collect::<Vec<&str>>());
+    return match machine.read_until_prompt().unwrap() {
+        Reply::Value(_, data) => Ok(data),
+        Reply::Halted(text) if text.contains("Didn't make it across") => Err(Failure::Fell(text)),
+        Reply::Halted(text) | Reply::Prompt(text) => Err(Failure::Rejected(text)),
+        Reply::Truncated(_) => unreachable!("Read without a limit"),
+    }
 }
 
-impl Controller {
-    pub fn new() -> Controller {
-        return Controller {
-            x: 0,
-            y: 0,
-            map: vec![vec![]],
-            robot: None,
+// Searches scripts for the hulls the droid fell on so far, adding the next hull until one works
+fn search_script(program: &Memory, mode: Mode, verbose: bool) -> Option<(springscript::Program, Word)> {
+    let mut rng = StdRng::seed_from_u64(21);
+    let mut hulls: Vec<String> = vec![];
+    loop {
+        let script = springscript::search(mode, &hulls, 2000, &mut rng)?;
+        let report = match run_script(program, &script) {
+            Ok(damage) => return Some((script, damage)),
+            Err(Failure::Fell(report)) => report,
+            Err(Failure::Rejected(_)) => return None,
         };
-    }
-
-    pub fn update(&mut self, w: &char) {
-        let y = self.map.len()-1;
-        match w {
-            '.' => {
-                self.map[y].push(Empty);
-            },
-            '#' =>  self.map[y].push(Scaffold),
-            '^' => {},
-            'v' => {},
-            '>' => {},
-            '<' => {},
-            '\n' => self.map.push(vec![]),
-            _ => {
-                // panic!("Unknown symbol: {}", w);
-            }
-
-
+        let hull = hull_from_report(&report)?;
+        if verbose {
+            println!("{} hulls, fell on {}", hulls.len(), hull);
         }
-    }
-
-
-    pub fn print(&self) {
-//        print!("{}[2J", 27 as char);
-//        print!("\x1B[2J");
-
-        for y in self.map.iter() {
-            for x in y {
-                print!("{}", x.repr());
-
+        // The simulator does not match the droid
+        if hulls.contains(&hull) {
+            if verbose {
+                println!("The simulated droid made it across {}", hull);
             }
-            println!("");
+            return None;
         }
+        hulls.push(hull);
     }
-
 }
 
-fn main() {
-    let filename = "data/day21/input.txt";
-    let mut program = intmachine::read_program(filename);
-
-//    let mut controller:Controller = Controller::new();
-
-    let (input, pin): (SyncSender<Message>, Receiver<Message>) = mpsc::sync_channel(0);
-    let (pout, output): (SyncSender<Message>, Receiver<Message>) = mpsc::sync_channel(0);
-
-    let child = thread::spawn(move || {
-        intmachine::execute(&program, pin, pout);
-    });
-
-    let mut rng = rand::thread_rng();
-
-    let mut count = 0;
-    /*
-    let instructions = vec![
-        "NOT A J",
-        "NOT B T",
-        "OR T J",
-        "NOT C T",
-        "OR T J",
-        "AND D J",
-        "WALK",
-    ];
-    */
-    let instructions = vec![
-        // Jump if A,B or C
-        "NOT A J",
-        "NOT B T",
-        "OR T J",
-        "NOT C T",
-        "OR T J",
-
-        // Don't jump to a hole
-        "AND D J",
-
-        // Don't jump to a place where there is nothing after, and must jump to hole
-        "NOT E T",
-        "NOT T T",
-        "OR H T",
-        "AND T J",
-
-        "RUN",
-    ];
-
-    let mut d: VecDeque<char> = VecDeque::new();
-    for line in instructions {
-        d.extend(line.chars());
-        d.push_back('\n');
+#[cfg(test)]
+mod tests {
+    use crate::{run_script, search_script, Failure, RUN, WALK};
+    use advent_of_code_2019::intmachine::read_program;
+    use advent_of_code_2019::springscript::{compile, hull_from_report, Mode, MAX_INSTRUCTIONS};
+
+    #[test]
+    fn test_scripts() {
+        let program = read_program("data/day21/input.txt");
+        assert_eq!(run_script(&program, &compile(WALK, Mode::Walk).unwrap()), Ok(19361414));
+        assert_eq!(run_script(&program, &compile(RUN, Mode::Run).unwrap()), Ok(1139205618));
+
+        // The simulator agrees with the droid on the hull it fell on
+        let script = compile("!A", Mode::Walk).unwrap();
+        let report = match run_script(&program, &script) {
+            Err(Failure::Fell(report)) => report,
+            result => panic!("Unexpected result: {:?}", result),
+        };
+        let hull = hull_from_report(&report).unwrap();
+        assert_eq!(script.check(std::slice::from_ref(&hull)).len(), 1);
+        assert!(compile(WALK, Mode::Walk).unwrap().simulate(&hull).survived());
     }
 
-
-
-    loop {
-
-        match output.recv() {
-            Ok(message) => {
-                match message {
-                    Message::Data(data) =>  {
-                        if data > 256 {
-                            println!("Received: {}", data);
-                        }
-                        let c = char::from(data as u8);
-//                        controller.update(&c);
-                        print!("{}", c);
-                    }
-
-                    Message::Shutdown => {
-                        println!("Shutdown..");
-                        break;
-                    }
-                    Message::RequestInput => {
-//                        println!("d: {:?}",d);
-                        let mc = d.pop_front();
-
-                        let c :char = mc.unwrap();
-
-//                        println!("sending: {}", &c);
-                        input.send(Message::Data(c as Word));
-
-//                        println!("Random walk: {}", &r);
-//                        input.send(Message::Data(r));
-//                        controller.state = WaitResponse(Direction::from(r));
-                    }
-                }
-            }
-            Err(error) => {
-                panic!("Error: {:?}", error.to_string());
-            }
+    #[test]
+    fn test_rejected() {
+        // The droid takes at most 15 instructions
+        let program = read_program("data/day21/input.txt");
+        let mut script = compile(WALK, Mode::Walk).unwrap();
+        script.instructions = vec![script.instructions[0]; MAX_INSTRUCTIONS + 1];
+        match run_script(&program, &script) {
+            Err(Failure::Rejected(_)) => {},
+            result => panic!("Unexpected result: {:?}", result),
         }
     }
-    child.join();
-
-    //controller.print();
-//    println!("Score: {}", controller.align());
-
-}
 
-#[cfg(test)]
-mod tests {
+    #[test]
+    fn test_search() {
+        let program = read_program("data/day21/input.txt");
+        assert_eq!(search_script(&program, Mode::Walk, false).map(|(_, damage)| damage), Some(19361414));
+    }
 
+    // Takes about half a minute in a debug build, springscript test_search covers the Run hulls
+    #[test]
+    #[ignore]
+    fn test_search_run() {
+        let program = read_program("data/day21/input.txt");
+        assert_eq!(search_script(&program, Mode::Run, false).map(|(_, damage)| damage), Some(1139205618));
+    }
 }
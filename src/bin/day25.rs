@@ -1,60 +1,642 @@
-use std::{thread};
-use advent_of_code_2019::intmachine;
-use std::collections::{HashMap, VecDeque};
-use std::sync::mpsc::{SyncSender, Receiver, Sender, RecvTimeoutError, RecvError};
-use advent_of_code_2019::intmachine::{Message, Word, IO, StandardIO};
-use std::sync::mpsc;
-use std::borrow::Borrow;
-use std::collections::hash_map::RandomState;
-use std::thread::sleep;
-use std::time::Duration;
-use rand::Rng;
-use std::io::BufRead;
+use std::env;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+use advent_of_code_2019::intmachine::{self, Memory};
+use advent_of_code_2019::ascii::{AsciiMachine, Reply};
+use petgraph::Graph;
+use petgraph::algo::astar;
+use petgraph::graph::NodeIndex;
+use regex::Regex;
 
+// Console for the droid. Besides the game commands it understands:
+//   take all, drop all    take every item in the room, drop everything carried
+//   history               list the commands entered so far
+//   !!, !<n>              repeat the last or the n:th command
+//   play <file>           run the commands in a file, one per line, scripts can play other scripts
+//   save <file>           write the history to a file, to be played back later
+//   quit
+struct Console {
+    machine: AsciiMachine,
+    history: Vec<String>,
+    transcript: Option<File>,
+    // Output since the last command
+    last_output: String,
+    // Scripts being played, a script playing itself again would never end
+    playing: Vec<PathBuf>,
+}
 
-fn main() {
+impl Console {
+    fn new(program: &Memory, transcript: Option<File>) -> Console {
+        return Console {
+            machine: AsciiMachine::new(program),
+            history: vec![],
+            transcript,
+            last_output: String::new(),
+            playing: vec![],
+        };
+    }
 
-    let filename = "data/day25/input.txt";
-    let mut program = intmachine::read_program(filename);
-
-    let (console_in, stdin) : (SyncSender<Message>, Receiver<Message>) = mpsc::sync_channel(0);
-    let (stdout, console_out): (SyncSender<Message>, Receiver<Message>) = mpsc::sync_channel(0);
-
-    let child = thread::spawn(move || {
-        let mut io = StandardIO { stdin, stdout };
-        intmachine::execute(&program, &mut io);
-    });
-    let output = thread::spawn(move || {
-        loop {
-            match console_out.recv() {
-                Ok(Message::Data(data)) => {
-                    print!("{}", (data as u8) as char);
+    // Prints the output up to the next prompt, false once the program halted
+    fn read(&mut self, out: &mut dyn Write) -> io::Result<bool> {
+        let mut text = String::new();
+        let running = loop {
+            match self.machine.read_until_prompt().unwrap() {
+                Reply::Prompt(t) => {
+                    text.push_str(&t);
+                    break true;
+                },
+                Reply::Value(t, data) => {
+                    text.push_str(&t);
+                    text.push_str(&format!("Received: {}\n", data));
                 },
-                Ok(Message::RequestInput) => {
+                Reply::Halted(t) => {
+                    text.push_str(&t);
+                    break false;
+                },
+                Reply::Truncated(_) => unreachable!("Read without a limit"),
+            }
+        };
+        self.print(out, &text)?;
+        self.last_output = text;
+        return Ok(running);
+    }
 
+    fn print(&mut self, out: &mut dyn Write, text: &str) -> io::Result<()> {
+        write!(out, "{}", text)?;
+        out.flush()?;
+        if let Some(transcript) = self.transcript.as_mut() {
+            write!(transcript, "{}", text)?;
+        }
+        return Ok(());
+    }
+
+    // Sends one command to the droid
+    fn send(&mut self, command: &str, out: &mut dyn Write) -> io::Result<bool> {
+        if let Some(transcript) = self.transcript.as_mut() {
+            writeln!(transcript, "{}", command)?;
+        }
+        self.machine.send_line(command);
+        return self.read(out);
+    }
+
+    // Runs a line typed at the console, false when the console should exit
+    fn command(&mut self, line: &str, out: &mut dyn Write) -> io::Result<bool> {
+        let line = line.trim();
+        if line.is_empty() {
+            return Ok(true);
+        }
+        if line == "history" {
+            let listing: String = self.history.iter().enumerate()
+                .map(|(i, c)| format!("{:>4}  {}\n", i + 1, c))
+                .collect();
+            self.print(out, &listing)?;
+            return Ok(true);
+        }
+        if let Some(number) = line.strip_prefix('!') {
+            let repeated = match number.parse::<usize>() {
+                Ok(n) if n >= 1 => self.history.get(n - 1).cloned(),
+                _ if line == "!!" => self.history.last().cloned(),
+                _ => None,
+            };
+            return match repeated {
+                Some(command) => {
+                    echo(out, &command)?;
+                    self.command(&command, out)
                 },
-                _ => {
-                    panic!("Unhandled output");
+                None => {
+                    self.print(out, &format!("No such command in history: {}\n", line))?;
+                    Ok(true)
                 },
             }
         }
-    });
+        if let Some(file) = line.strip_prefix("play ") {
+            let (script, path) = match (fs::read_to_string(file), fs::canonicalize(file)) {
+                (Ok(script), Ok(path)) => (script, path),
+                (Err(e), _) | (_, Err(e)) => {
+                    self.print(out, &format!("Can not read {}: {}\n", file, e))?;
+                    return Ok(true);
+                },
+            };
+            if self.playing.contains(&path) {
+                self.print(out, &format!("Already playing {}\n", file))?;
+                return Ok(true);
+            }
+            self.playing.push(path);
+            let running = self.play(&script, out);
+            self.playing.pop();
+            return running;
+        }
+        if let Some(file) = line.strip_prefix("save ") {
+            let mut script = self.history.join("\n");
+            script.push('\n');
+            if let Err(e) = fs::write(file, script) {
+                self.print(out, &format!("Can not write {}: {}\n", file, e))?;
+            }
+            return Ok(true);
+        }
+        if line == "quit" {
+            return Ok(false);
+        }
+
+        self.history.push(line.to_string());
+        return match line {
+            "take all" => {
+                let items = parse_list(&self.last_output, "Items here:");
+                self.each(&items, "take", out)
+            },
+            "drop all" => {
+                if !self.send("inv", out)? {
+                    return Ok(false);
+                }
+                let items = parse_list(&self.last_output, "Items in your inventory:");
+                self.each(&items, "drop", out)
+            },
+            _ => self.send(line, out),
+        }
+    }
 
-    loop {
-        for line in std::io::stdin().lock().lines() {
-            for c in line.unwrap().chars() {
-                console_in.send(Message::Data(c as Word));
+    fn each(&mut self, items: &[String], verb: &str, out: &mut dyn Write) -> io::Result<bool> {
+        for item in items {
+            let command = format!("{} {}", verb, item);
+            echo(out, &command)?;
+            if !self.send(&command, out)? {
+                return Ok(false);
             }
-            console_in.send(Message::Data('\n' as Word));
         }
+        return Ok(true);
+    }
+
+    // Runs every line of a script, echoing the commands
+    fn play(&mut self, script: &str, out: &mut dyn Write) -> io::Result<bool> {
+        for line in script.lines().filter(|l| !l.trim().is_empty()) {
+            echo(out, line.trim())?;
+            if !self.command(line, out)? {
+                return Ok(false);
+            }
+        }
+        return Ok(true);
+    }
+}
+
+// Shows commands that were not typed, they go to the transcript when sent
+fn echo(out: &mut dyn Write, command: &str) -> io::Result<()> {
+    writeln!(out, "{}", command)?;
+    return out.flush();
+}
+
+// The "- " entries following the header line
+fn parse_list(text: &str, header: &str) -> Vec<String> {
+    return text.lines()
+        .skip_while(|l| *l != header)
+        .skip(1)
+        .take_while(|l| l.starts_with("- "))
+        .map(|l| l.trim_start_matches("- ").to_string())
+        .collect();
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Room {
+    name: String,
+    doors: Vec<String>,
+    items: Vec<String>,
+}
+
+impl fmt::Display for Room {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "{}", self.name);
+    }
+}
+
+// Every room described in the text, the droid ends up in the last one
+fn parse_rooms(text: &str) -> Vec<Room> {
+    let lines: Vec<&str> = text.lines().collect();
+    let headers: Vec<usize> = (0..lines.len())
+        .filter(|i| lines[*i].starts_with("== ") && lines[*i].ends_with(" =="))
+        .collect();
+    return headers.iter().enumerate().map(|(n, start)| {
+        let end = headers.get(n + 1).cloned().unwrap_or(lines.len());
+        let description = lines[*start..end].join("\n");
+        Room {
+            name: lines[*start].trim_matches(|c| c == '=' || c == ' ').to_string(),
+            doors: parse_list(&description, "Doors here lead:"),
+            items: parse_list(&description, "Items here:"),
+        }
+    }).collect();
+}
+
+fn opposite(door: &str) -> &'static str {
+    return match door {
+        "north" => "south",
+        "south" => "north",
+        "east" => "west",
+        "west" => "east",
+        _ => panic!("Unknown door: {}", door),
     }
+}
 
-    child.join();
-    output.join();
+fn offset(door: &str) -> (i32, i32) {
+    return match door {
+        "north" => (0, -1),
+        "south" => (0, 1),
+        "east" => (1, 0),
+        "west" => (-1, 0),
+        _ => panic!("Unknown door: {}", door),
+    }
+}
 
+// Output of taking a looping item is endless, normal replies are far shorter
+const REPLY_LIMIT: usize = 5000;
+
+// Plays the adventure by itself: maps the ship, collects every safe item and finds the
+// combination of items that the pressure-sensitive floor accepts
+struct Explorer {
+    machine: AsciiMachine,
+    map: Graph<Room, String>,
+    rooms: HashMap<String, NodeIndex>,
+    current: NodeIndex,
+    carried: Vec<String>,
+    dangerous: Vec<String>,
+    // Room and door leading to the pressure-sensitive floor
+    checkpoint: Option<(NodeIndex, String)>,
+}
+
+impl Explorer {
+    fn new(program: &Memory) -> Explorer {
+        let mut machine = AsciiMachine::new(program);
+        let text = machine.read_until_prompt().unwrap().text().to_string();
+        let room = parse_rooms(&text).pop().expect("No starting room");
+        let mut map = Graph::new();
+        let mut rooms = HashMap::new();
+        let current = map.add_node(room.clone());
+        rooms.insert(room.name, current);
+        return Explorer { machine, map, rooms, current, carried: vec![], dangerous: vec![], checkpoint: None };
+    }
+
+    fn send(&mut self, command: &str) -> Reply {
+        self.machine.send_line(command);
+        return match self.machine.read_at_most(REPLY_LIMIT).unwrap() {
+            Reply::Truncated(text) => panic!("Endless reply: {}", text),
+            reply => reply,
+        }
+    }
+
+    // Tries the item on a copy of the droid
+    fn is_safe(&self, item: &str, door: &str) -> bool {
+        let mut probe = self.machine.clone();
+        probe.send_line(&format!("take {}", item));
+        match probe.read_at_most(REPLY_LIMIT).unwrap() {
+            Reply::Prompt(_) => {},
+            _ => return false,
+        }
+        probe.send_line(door);
+        return match probe.read_at_most(REPLY_LIMIT).unwrap() {
+            Reply::Prompt(text) => !text.contains("can't move"),
+            _ => false,
+        }
+    }
+
+    fn take_items(&mut self) {
+        let room = self.map[self.current].clone();
+        for item in room.items.iter() {
+            if !self.is_safe(item, &room.doors[0]) {
+                self.dangerous.push(item.clone());
+                continue;
+            }
+            self.send(&format!("take {}", item));
+            self.carried.push(item.clone());
+        }
+    }
+
+    // Depth first through every door, returning to the room it started in
+    fn explore(&mut self) {
+        self.take_items();
+        let here = self.current;
+        let doors = self.map[here].doors.clone();
+        for door in doors {
+            if self.map.edges(here).any(|e| e.weight() == &door) {
+                continue;
+            }
+            let text = self.send(&door).text().to_string();
+            let rooms = parse_rooms(&text);
+            let (first, last) = (rooms[0].clone(), rooms[rooms.len() - 1].clone());
+            if last.name == self.map[here].name {
+                // Sent back, the door leads to the pressure-sensitive floor
+                let floor = self.map.add_node(Room { doors: vec![opposite(&door).to_string()], ..first });
+                self.map.add_edge(here, floor, door.clone());
+                self.map.add_edge(floor, here, opposite(&door).to_string());
+                self.checkpoint = Some((here, door));
+                continue;
+            }
+            let known = self.rooms.contains_key(&last.name);
+            let next = match self.rooms.get(&last.name) {
+                Some(next) => *next,
+                None => self.map.add_node(last.clone()),
+            };
+            self.rooms.insert(last.name.clone(), next);
+            self.map.add_edge(here, next, door.clone());
+            self.map.add_edge(next, here, opposite(&door).to_string());
+            self.current = next;
+            if !known {
+                self.explore();
+            }
+            self.send(opposite(&door));
+            self.current = here;
+        }
+    }
+
+    fn walk_to(&mut self, target: NodeIndex) {
+        let (_, path) = astar(&self.map, self.current, |n| n == target, |_| 1, |_| 0).expect("No path");
+        for step in path.windows(2) {
+            let edge = self.map.find_edge(step[0], step[1]).unwrap();
+            let door = self.map[edge].clone();
+            self.send(&door);
+        }
+        self.current = target;
+    }
+
+    // Tries item combinations on the floor, skipping supersets of too heavy and subsets of
+    // too light combinations. Returns the password.
+    fn pass_checkpoint(&mut self) -> Option<String> {
+        let (checkpoint, door) = self.checkpoint.clone()?;
+        self.walk_to(checkpoint);
+        let items = self.carried.clone();
+        let mut holding: u32 = (1 << items.len()) - 1;
+        let mut too_heavy: Vec<u32> = vec![];
+        let mut too_light: Vec<u32> = vec![];
+        let password = Regex::new(r"typing (\d+)").unwrap();
+        for mask in 0..(1u32 << items.len()) {
+            if too_heavy.iter().any(|h| mask & h == *h) || too_light.iter().any(|l| mask & l == mask) {
+                continue;
+            }
+            for (i, item) in items.iter().enumerate() {
+                let bit = 1 << i;
+                if mask & bit != 0 && holding & bit == 0 {
+                    self.send(&format!("take {}", item));
+                } else if mask & bit == 0 && holding & bit != 0 {
+                    self.send(&format!("drop {}", item));
+                }
+            }
+            holding = mask;
+            self.carried = items.iter().enumerate().filter(|(i, _)| mask & (1 << i) != 0).map(|(_, item)| item.clone()).collect();
+            let text = self.send(&door).text().to_string();
+            if text.contains("heavier than the detected") {
+                too_light.push(mask);
+            } else if text.contains("lighter than the detected") {
+                too_heavy.push(mask);
+            } else {
+                return password.captures(&text).map(|c| c[1].to_string());
+            }
+        }
+        return None;
+    }
+
+    // Places rooms breadth first from start, skipping rooms whose cell is already taken
+    fn layout(&self, start: NodeIndex) -> HashMap<NodeIndex, (i32, i32)> {
+        let mut positions: HashMap<NodeIndex, (i32, i32)> = HashMap::new();
+        let mut taken: HashSet<(i32, i32)> = HashSet::new();
+        positions.insert(start, (0, 0));
+        taken.insert((0, 0));
+        let mut queue = VecDeque::new();
+        queue.push_back(start);
+        while let Some(node) = queue.pop_front() {
+            let (x, y) = positions[&node];
+            for edge in self.map.edges(node) {
+                let next = petgraph::visit::EdgeRef::target(&edge);
+                let (dx, dy) = offset(edge.weight());
+                let cell = (x + dx, y + dy);
+                if positions.contains_key(&next) || taken.contains(&cell) {
+                    continue;
+                }
+                positions.insert(next, cell);
+                taken.insert(cell);
+                queue.push_back(next);
+            }
+        }
+        return positions;
+    }
+
+    // Rooms laid out by the direction of their doors, numbered as in the legend. The ship does
+    // not always fit a grid, rooms that would overlap another are only listed in the legend.
+    fn render(&self) -> String {
+        let positions = self.map.node_indices()
+            .map(|start| self.layout(start))
+            .max_by_key(|positions| positions.len())
+            .unwrap();
+        let cells: HashMap<(i32, i32), NodeIndex> = positions.iter().map(|(n, p)| (*p, *n)).collect();
+        // Only draw doors between rooms that are neighbours on the grid
+        let connected = |cell: (i32, i32), door: &str| {
+            let (dx, dy) = offset(door);
+            return match (cells.get(&cell), cells.get(&(cell.0 + dx, cell.1 + dy))) {
+                (Some(a), Some(b)) => matches!(self.map.find_edge(*a, *b), Some(e) if self.map[e] == door),
+                _ => false,
+            };
+        };
+        let x_min = positions.values().map(|p| p.0).min().unwrap();
+        let x_max = positions.values().map(|p| p.0).max().unwrap();
+        let y_min = positions.values().map(|p| p.1).min().unwrap();
+        let y_max = positions.values().map(|p| p.1).max().unwrap();
+
+        let mut out = String::new();
+        for y in y_min..=y_max {
+            let mut rooms = String::new();
+            let mut doors = String::new();
+            for x in x_min..=x_max {
+                match cells.get(&(x, y)) {
+                    Some(node) => rooms.push_str(&format!("[{:>2}]", node.index())),
+                    None => rooms.push_str("    "),
+                }
+                rooms.push(if connected((x, y), "east") { '-' } else { ' ' });
+                doors.push_str(if connected((x, y), "south") { "  | " } else { "    " });
+                doors.push(' ');
+            }
+            out.push_str(rooms.trim_end());
+            out.push('\n');
+            if y < y_max {
+                out.push_str(doors.trim_end());
+                out.push('\n');
+            }
+        }
+        out.push('\n');
+        for node in self.map.node_indices() {
+            let room = &self.map[node];
+            out.push_str(&format!("{:>2}  {}", node.index(), room.name));
+            if !room.items.is_empty() {
+                out.push_str(&format!(" ({})", room.items.join(", ")));
+            }
+            if !positions.contains_key(&node) {
+                let doors: Vec<String> = self.map.edges(node)
+                    .map(|e| format!("{} to {}", e.weight(), petgraph::visit::EdgeRef::target(&e).index()))
+                    .collect();
+                out.push_str(&format!(", not drawn: {}", doors.join(", ")));
+            }
+            out.push('\n');
+        }
+        return out;
+    }
+}
+
+// Returns the password and the map
+fn solve(program: &Memory) -> (Option<String>, String) {
+    let mut explorer = Explorer::new(program);
+    explorer.explore();
+    let password = explorer.pass_checkpoint();
+    let mut map = explorer.render();
+    map.push_str(&format!("\nDangerous items: {}\n", explorer.dangerous.join(", ")));
+    map.push_str(&format!("Items for the floor: {}\n", explorer.carried.join(", ")));
+    return (password, map);
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let mut filename = "data/day25/input.txt".to_string();
+    let mut script = None;
+    let mut transcript = None;
+    let mut solving = false;
+    let mut i = 1;
+    while i < args.len() {
+        if args[i] == "--solve" {
+            solving = true;
+            i += 1;
+            continue;
+        }
+        match (args[i].as_str(), args.get(i + 1)) {
+            ("--script", Some(file)) => script = Some(file.clone()),
+            ("--transcript", Some(file)) => transcript = Some(File::create(file).unwrap()),
+            ("--program", Some(file)) => filename = file.clone(),
+            _ => {
+                println!("Usage: {} [--program <file>] [--solve] [--script <file>] [--transcript <file>]", args[0]);
+                return;
+            },
+        }
+        i += 2;
+    }
+
+    let program = intmachine::read_program(&filename);
+    if solving {
+        let (password, map) = solve(&program);
+        print!("{}", map);
+        match password {
+            Some(password) => println!("Password: {}", password),
+            None => println!("No item combination passed the checkpoint"),
+        }
+        return;
+    }
+    let mut console = Console::new(&program, transcript);
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+
+    let mut running = console.read(&mut out).unwrap();
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    let mut next = script.map(|file| format!("play {}", file));
+    while running {
+        let line = match next.take().or_else(|| lines.next().map(|line| line.unwrap())) {
+            Some(line) => line,
+            None => break,
+        };
+        // A failing command, like a transcript that can not be written, does not end the game
+        running = match console.command(&line, &mut out) {
+            Ok(running) => running,
+            Err(e) => {
+                println!("Error: {}", e);
+                true
+            },
+        };
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::{parse_list, parse_rooms, solve, Console, Room};
+    use advent_of_code_2019::intmachine::read_program;
+    use std::env;
+    use std::fs::{self, File};
+
+    #[test]
+    fn test_parse_list() {
+        let text = "== Room ==\n\nDoors here lead:\n- north\n- west\n\nItems here:\n- coin\n- mug\n\nCommand?\n";
+        assert_eq!(parse_list(text, "Doors here lead:"), vec!["north", "west"]);
+        assert_eq!(parse_list(text, "Items here:"), vec!["coin", "mug"]);
+        assert_eq!(parse_list(text, "Items in your inventory:"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_console() {
+        let program = read_program("data/day25/input.txt");
+        let transcript = env::temp_dir().join("day25_transcript.txt");
+        let script = env::temp_dir().join("day25_script.txt");
+        let mut console = Console::new(&program, Some(File::create(&transcript).unwrap()));
+        let mut out: Vec<u8> = vec![];
+        assert!(console.read(&mut out).unwrap());
+        assert!(console.command("north", &mut out).unwrap());
+        assert!(console.command("take all", &mut out).unwrap());
+        assert!(console.command("inv", &mut out).unwrap());
+        assert!(console.last_output.contains("- coin"));
+        assert!(console.command("drop all", &mut out).unwrap());
+        assert!(console.last_output.contains("You drop the coin."));
+        assert!(console.command("!3", &mut out).unwrap());
+        assert!(console.last_output.contains("You aren't carrying any items."));
+        assert_eq!(console.history, vec!["north", "take all", "inv", "drop all", "inv"]);
+        assert!(console.command(&format!("save {}", script.display()), &mut out).unwrap());
+        assert!(!console.command("quit", &mut out).unwrap());
+
+        // The saved script brings a new console to the same place
+        let mut replay = Console::new(&program, None);
+        replay.read(&mut vec![]).unwrap();
+        assert!(replay.command(&format!("play {}", script.display()), &mut vec![]).unwrap());
+        assert_eq!(replay.history, console.history);
+        assert_eq!(replay.last_output, console.last_output);
+
+        let recorded = fs::read_to_string(&transcript).unwrap();
+        assert!(recorded.contains("== Hull Breach =="));
+        assert!(recorded.contains("take coin\n"));
+        fs::remove_file(transcript).unwrap();
+        fs::remove_file(script).unwrap();
+    }
 
-}
\ No newline at end of file
+    #[test]
+    fn test_play_errors() {
+        let program = read_program("data/day25/input.txt");
+        let script = env::temp_dir().join("day25_recursive.txt");
+        fs::write(&script, format!("north\nplay {}\n", script.display())).unwrap();
+        let mut console = Console::new(&program, None);
+        let mut out: Vec<u8> = vec![];
+        console.read(&mut out).unwrap();
+
+        // Errors are reported and the console keeps running
+        assert!(console.command("play /nonexistent/script.txt", &mut out).unwrap());
+        assert!(console.command(&format!("save {}", env::temp_dir().display()), &mut out).unwrap());
+        assert!(console.command(&format!("play {}", script.display()), &mut out).unwrap());
+        assert_eq!(console.history, vec!["north"]);
+        assert!(console.playing.is_empty());
+        let printed = String::from_utf8(out).unwrap();
+        assert!(printed.contains("Can not read /nonexistent/script.txt: "));
+        assert!(printed.contains(&format!("Can not write {}: ", env::temp_dir().display())));
+        assert!(printed.contains(&format!("Already playing {}\n", script.display())));
+        fs::remove_file(script).unwrap();
+    }
+
+    #[test]
+    fn test_parse_rooms() {
+        let text = "\n\n\n== Pressure-Sensitive Floor ==\nAnalyzing...\n\nDoors here lead:\n- west\n\n\
+                    A loud, robotic voice says \"Alert!\" and you are ejected back to the checkpoint.\n\n\n\n\
+                    == Security Checkpoint ==\nIn the next room, a pressure-sensitive floor will verify your identity.\n\n\
+                    Doors here lead:\n- north\n- east\n\nCommand?\n";
+        let rooms = parse_rooms(text);
+        assert_eq!(rooms.len(), 2);
+        assert_eq!(rooms[0], Room { name: "Pressure-Sensitive Floor".to_string(), doors: vec!["west".to_string()], items: vec![] });
+        assert_eq!(rooms[1].name, "Security Checkpoint");
+        assert_eq!(rooms[1].doors, vec!["north", "east"]);
+    }
+
+    #[test]
+    fn test_solve() {
+        let (password, map) = solve(&read_program("data/day25/input.txt"));
+        assert_eq!(password, Some("16410".to_string()));
+        assert!(map.contains(" 0  Hull Breach\n"));
+        assert!(map.contains("Dangerous items: escape pod, infinite loop, molten lava, photons, giant electromagnet\n"));
+    }
+}
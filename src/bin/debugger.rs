@@ -0,0 +1,160 @@
+use std::{env, thread};
+use std::collections::VecDeque;
+use std::io::{BufRead, Write, stdout, stdin};
+use std::sync::mpsc;
+use std::sync::mpsc::{Receiver, Sender, SyncSender};
+use advent_of_code_2019::intmachine;
+use advent_of_code_2019::intmachine::{Message, Word, IO};
+use advent_of_code_2019::debugger::{DebugCommand, DebugEvent, serve};
+
+// Program IO on the console. Numbers are sent as they are, other lines as ASCII.
+struct ConsoleIO {
+    pending: VecDeque<Word>,
+}
+
+impl IO for ConsoleIO {
+    fn send(&mut self, message: Message) {
+        match message {
+            Message::Data(data) => println!("output: {}", data),
+            Message::Shutdown => println!("program halted"),
+            Message::RequestInput => {},
+        }
+    }
+
+    fn receive(&mut self) -> Message {
+        while self.pending.is_empty() {
+            print!("input> ");
+            stdout().flush().unwrap();
+            let mut line = String::new();
+            stdin().lock().read_line(&mut line).unwrap();
+            let line = line.trim_end_matches('\n');
+            match line.trim().parse::<Word>() {
+                Ok(v) => self.pending.push_back(v),
+                Err(_) => {
+                    self.pending.extend(line.chars().map(|c| c as Word));
+                    self.pending.push_back('\n' as Word);
+                }
+            }
+        }
+        return Message::Data(self.pending.pop_front().unwrap());
+    }
+}
+
+fn help() {
+    println!("Commands:");
+    println!("  s [n]          step n instructions");
+    println!("  c              continue");
+    println!("  b <addr>       set breakpoint, db <addr> to delete");
+    println!("  w <addr>       set watchpoint, dw <addr> to delete");
+    println!("  r              show ip and relative base");
+    println!("  ip <addr>      set ip, rb <value> sets the relative base");
+    println!("  x <addr> [n]   show n memory cells");
+    println!("  set <addr> <v> write to memory");
+    println!("  q              quit");
+}
+
+fn parse_command(line: &str) -> Option<(DebugCommand, usize)> {
+    let parts: Vec<&str> = line.split_whitespace().collect();
+    let arg = |i: usize| parts.get(i).and_then(|a| a.parse::<Word>().ok());
+    let command = match parts.first().cloned() {
+        Some("s") => return Some((DebugCommand::Step, arg(1).unwrap_or(1) as usize)),
+        Some("c") => DebugCommand::Continue,
+        Some("b") => DebugCommand::SetBreakpoint(arg(1)?),
+        Some("db") => DebugCommand::ClearBreakpoint(arg(1)?),
+        Some("w") => DebugCommand::SetWatchpoint(arg(1)?),
+        Some("dw") => DebugCommand::ClearWatchpoint(arg(1)?),
+        Some("r") => DebugCommand::State,
+        Some("x") => DebugCommand::Peek(arg(1)?, arg(2).unwrap_or(1)),
+        Some("set") => DebugCommand::Poke(arg(1)?, arg(2)?),
+        Some("q") => DebugCommand::Quit,
+        _ => return None,
+    };
+    return Some((command, 1));
+}
+
+fn print_event(event: &DebugEvent) {
+    match event {
+        DebugEvent::Stopped { reason, state, next } => {
+            println!("{:?}  ip: {} rb: {}", reason, state.ip, state.relative_base);
+            match next {
+                Some(instruction) => println!("{:04}  {}", state.ip, instruction),
+                None => println!("{:04}  <invalid instruction>", state.ip),
+            }
+        },
+        DebugEvent::State(state) => println!("ip: {} rb: {}", state.ip, state.relative_base),
+        DebugEvent::Memory(address, values) => {
+            for (i, v) in values.iter().enumerate() {
+                println!("{:04}  {}", address + i as Word, v);
+            }
+        },
+        DebugEvent::Done => {},
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        println!("Usage: {} <program>", args[0]);
+        return;
+    }
+    let program = intmachine::read_program(&args[1]);
+
+    let (commands, command_receiver): (Sender<DebugCommand>, Receiver<DebugCommand>) = mpsc::channel();
+    let (event_sender, events): (SyncSender<DebugEvent>, Receiver<DebugEvent>) = mpsc::sync_channel(0);
+
+    let child = thread::spawn(move || {
+        let mut io = ConsoleIO { pending: VecDeque::new() };
+        serve(&program, command_receiver, event_sender, &mut io);
+    });
+
+    help();
+    loop {
+        print!("(debug) ");
+        stdout().flush().unwrap();
+        let mut line = String::new();
+        if stdin().lock().read_line(&mut line).unwrap() == 0 {
+            commands.send(DebugCommand::Quit).unwrap();
+            events.recv().unwrap();
+            break;
+        }
+
+        // ip and rb are changed by updating the whole processor state
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        if parts.len() == 2 && (parts[0] == "ip" || parts[0] == "rb") {
+            let value = match parts[1].parse::<Word>() {
+                Ok(v) => v,
+                Err(_) => {
+                    help();
+                    continue;
+                }
+            };
+            commands.send(DebugCommand::State).unwrap();
+            if let DebugEvent::State(mut state) = events.recv().unwrap() {
+                if parts[0] == "ip" {
+                    state.ip = value;
+                } else {
+                    state.relative_base = value;
+                }
+                commands.send(DebugCommand::SetState(state)).unwrap();
+                events.recv().unwrap();
+            }
+            continue;
+        }
+
+        let (command, repeat) = match parse_command(&line) {
+            Some(c) => c,
+            None => {
+                help();
+                continue;
+            }
+        };
+        for _ in 0..repeat {
+            commands.send(command.clone()).unwrap();
+            print_event(&events.recv().unwrap());
+        }
+        if command == DebugCommand::Quit {
+            break;
+        }
+    }
+    child.join().unwrap();
+}
@@ -0,0 +1,19 @@
+use std::env;
+use advent_of_code_2019::intmachine;
+use advent_of_code_2019::disassembler;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        println!("Usage: {} <program> [entry point]...", args[0]);
+        return;
+    }
+    let program = intmachine::read_program(&args[1]);
+
+    let mut entry_points: Vec<intmachine::Word> = args[2..].iter().map(|a| a.parse().unwrap()).collect();
+    if entry_points.is_empty() {
+        entry_points.push(0);
+    }
+    let listing = disassembler::disassemble_from(&program, &entry_points);
+    print!("{}", listing);
+}
@@ -0,0 +1,257 @@
+// Control flow graph of an Intcode program, built on the code found by the disassembler.
+//
+// Blocks start at entry points, jump targets, return addresses and after every jump or halt.
+// Jumps with an immediate target get an edge to that block, jumps through memory get an edge
+// to a single Indirect node. to_dot() renders the graph for Graphviz.
+
+use crate::disassembler::{disassemble_from, Entry, Listing};
+use crate::intmachine::{Instruction, Memory, Parameter, Word};
+use crate::intmachine::Instruction::{Halt, JumpIfTrue, JumpIfFalse};
+use petgraph::Graph;
+use petgraph::dot::Dot;
+use petgraph::graph::NodeIndex;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BasicBlock {
+    pub start: Word,
+    pub instructions: Vec<(Word, Instruction)>,
+}
+
+impl BasicBlock {
+    // First address after the block
+    pub fn end(&self) -> Word {
+        return match self.instructions.last() {
+            Some((address, instruction)) => address + instruction.size(),
+            None => self.start,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum CfgNode {
+    Block(BasicBlock),
+    // Target of all jumps through memory
+    Indirect,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum EdgeKind {
+    FallThrough,
+    Jump,
+    Indirect,
+}
+
+pub struct Cfg {
+    pub graph: Graph<CfgNode, EdgeKind>,
+    blocks: BTreeMap<Word, NodeIndex>,
+}
+
+impl Cfg {
+    pub fn block(&self, start: Word) -> Option<&BasicBlock> {
+        return match self.graph.node_weight(*self.blocks.get(&start)?) {
+            Some(CfgNode::Block(block)) => Some(block),
+            _ => None,
+        }
+    }
+
+    pub fn blocks(&self) -> Vec<&BasicBlock> {
+        return self.blocks.keys().filter_map(|start| self.block(*start)).collect();
+    }
+
+    // Start addresses of the blocks control can pass to from the block at start
+    pub fn successors(&self, start: Word) -> Vec<(EdgeKind, Option<Word>)> {
+        let node = match self.blocks.get(&start) {
+            Some(node) => *node,
+            None => return vec![],
+        };
+        let mut successors: Vec<(EdgeKind, Option<Word>)> = self.graph.edges(node).map(|e| {
+            let target = match self.graph.node_weight(petgraph::visit::EdgeRef::target(&e)) {
+                Some(CfgNode::Block(block)) => Some(block.start),
+                _ => None,
+            };
+            (*e.weight(), target)
+        }).collect();
+        successors.sort_by_key(|(_, target)| *target);
+        return successors;
+    }
+
+    pub fn to_dot(&self) -> String {
+        return format!("{}", Dot::new(&self.graph));
+    }
+}
+
+impl fmt::Display for CfgNode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            CfgNode::Block(block) => {
+                for (address, instruction) in block.instructions.iter() {
+                    writeln!(f, "{:04}  {}", address, instruction)?;
+                }
+                Ok(())
+            },
+            CfgNode::Indirect => write!(f, "indirect"),
+        }
+    }
+}
+
+impl fmt::Display for EdgeKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            EdgeKind::FallThrough => write!(f, ""),
+            EdgeKind::Jump => write!(f, "jump"),
+            EdgeKind::Indirect => write!(f, "indirect"),
+        }
+    }
+}
+
+pub fn control_flow_graph(memory: &Memory) -> Cfg {
+    return control_flow_graph_from(memory, &[0]);
+}
+
+pub fn control_flow_graph_from(memory: &Memory, entry_points: &[Word]) -> Cfg {
+    let listing = disassemble_from(memory, entry_points);
+    return from_listing(&listing, entry_points);
+}
+
+// Whether a jump can be taken and whether it can fall through, decided by an immediate condition
+fn jump_exits(instruction: &Instruction) -> (bool, bool) {
+    let (cond, jump_on_true) = match instruction {
+        JumpIfTrue { cond, .. } => (cond, true),
+        JumpIfFalse { cond, .. } => (cond, false),
+        Halt => return (false, false),
+        _ => return (false, true),
+    };
+    return match cond {
+        Parameter::Imm(v) => ((*v != 0) == jump_on_true, (*v != 0) != jump_on_true),
+        _ => (true, true),
+    }
+}
+
+fn from_listing(listing: &Listing, entry_points: &[Word]) -> Cfg {
+    let mut leaders: HashSet<Word> = entry_points.iter().cloned().collect();
+    leaders.extend(listing.labels.keys());
+
+    // Split the code into blocks
+    let mut blocks: Vec<BasicBlock> = vec![];
+    let mut previous: Option<(Word, Instruction)> = None;
+    for entry in listing.entries.iter() {
+        let (address, instruction) = match entry {
+            Entry::Code { address, instruction } => (*address, *instruction),
+            Entry::Data { .. } => {
+                previous = None;
+                continue;
+            },
+        };
+        let starts_block = match previous {
+            None => true,
+            Some((_, p)) => leaders.contains(&address) || jump_exits(&p) != (false, true),
+        };
+        if starts_block {
+            blocks.push(BasicBlock { start: address, instructions: vec![] });
+        }
+        blocks.last_mut().unwrap().instructions.push((address, instruction));
+        previous = Some((address, instruction));
+    }
+
+    let mut graph = Graph::new();
+    let mut nodes = BTreeMap::new();
+    for block in blocks.iter() {
+        nodes.insert(block.start, graph.add_node(CfgNode::Block(block.clone())));
+    }
+
+    let mut indirect = None;
+    for block in blocks.iter() {
+        let node = nodes[&block.start];
+        let (_, last) = *block.instructions.last().unwrap();
+        let (may_jump, may_continue) = jump_exits(&last);
+        if may_jump {
+            match last.params()[1] {
+                Parameter::Imm(target) => {
+                    if let Some(target) = nodes.get(&target) {
+                        graph.add_edge(node, *target, EdgeKind::Jump);
+                    }
+                },
+                _ => {
+                    let target = *indirect.get_or_insert_with(|| graph.add_node(CfgNode::Indirect));
+                    graph.add_edge(node, target, EdgeKind::Indirect);
+                },
+            }
+        }
+        if may_continue {
+            if let Some(next) = nodes.get(&block.end()) {
+                graph.add_edge(node, *next, EdgeKind::FallThrough);
+            }
+        }
+    }
+    return Cfg { graph, blocks: nodes };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cfg::{control_flow_graph, EdgeKind};
+    use crate::assembler::assemble;
+    use crate::intmachine::read_program;
+    use crate::intmachine::Instruction::{Equals, JumpIfFalse};
+    use crate::intmachine::Parameter::{Imm, Pos, Rel};
+
+    #[test]
+    fn test_blocks() {
+        let program = assemble("
+                in   [x]
+        loop:   add  [x], -1, [x]
+                out  [x]
+                jt   [x], loop
+                add  back, 0, [ret]
+                jt   1, func
+        back:   hlt
+        func:   out  7
+                jt   1, [ret]
+        x:      data 0
+        ret:    data 0
+        ").unwrap();
+        let cfg = control_flow_graph(&program);
+        let starts: Vec<i64> = cfg.blocks().iter().map(|b| b.start).collect();
+        assert_eq!(starts, vec![0, 2, 11, 18, 19]);
+        assert_eq!(cfg.block(2).unwrap().instructions.len(), 3);
+        assert_eq!(cfg.block(2).unwrap().end(), 11);
+
+        assert_eq!(cfg.successors(0), vec![(EdgeKind::FallThrough, Some(2))]);
+        assert_eq!(cfg.successors(2), vec![(EdgeKind::Jump, Some(2)), (EdgeKind::FallThrough, Some(11))]);
+        assert_eq!(cfg.successors(11), vec![(EdgeKind::Jump, Some(19))]);
+        assert_eq!(cfg.successors(18), vec![]);
+        assert_eq!(cfg.successors(19), vec![(EdgeKind::Indirect, None)]);
+
+        let dot = cfg.to_dot();
+        assert!(dot.starts_with("digraph {"));
+        assert!(dot.contains("indirect"));
+        assert!(dot.contains("0002  add  [24], -1, [24]\\l0006  out  [24]"));
+    }
+
+    #[test]
+    fn test_day21() {
+        let cfg = control_flow_graph(&read_program("data/day21/input.txt"));
+        assert!(cfg.blocks().len() > 10);
+        assert!(cfg.graph.edge_count() >= cfg.blocks().len() - 1);
+
+        // The entry block sets up the stack and calls the print routine with jf 0, 1378
+        let entry = cfg.block(0).unwrap();
+        assert_eq!(entry.instructions.iter().map(|(a, _)| *a).collect::<Vec<_>>(), vec![0, 2, 6, 10]);
+        assert_eq!(entry.instructions[3].1, JumpIfFalse { cond: Imm(0), target: Imm(1378) });
+        assert_eq!(entry.end(), 13);
+        assert_eq!(cfg.successors(0), vec![(EdgeKind::Jump, Some(1378))]);
+        assert_eq!(cfg.block(1378).unwrap().end(), 1396);
+
+        // The calls that follow return to the next block, which is only reached through memory
+        assert_eq!(cfg.block(13).unwrap().end(), 20);
+        assert_eq!(cfg.successors(13), vec![(EdgeKind::Jump, Some(1337))]);
+        assert_eq!(cfg.successors(20), vec![(EdgeKind::Jump, Some(1279))]);
+        assert_eq!(cfg.successors(1279), vec![(EdgeKind::Jump, Some(1263))]);
+
+        // Checking the first input for 'A' branches both ways
+        assert_eq!(cfg.block(27).unwrap().instructions[0].1, Equals { op1: Rel(1), op2: Imm(65), dst: Pos(748) });
+        assert_eq!(cfg.successors(27), vec![(EdgeKind::FallThrough, Some(34)), (EdgeKind::Jump, Some(73))]);
+        assert_eq!(cfg.block(31), None);
+    }
+}
@@ -0,0 +1,351 @@
+// Step debugger for Intcode programs.
+//
+// The Debugger can be used directly, or driven over channels with serve() so that it
+// runs in the same thread layout as the other machines.
+
+use crate::intmachine::{Instruction, IntcodeError, Memory, PagedMemory, ProcessorState, Word, IO, load_program, execute_step, try_decode_instruction, word_at};
+use crate::opcodes::{CustomOpcode, OpcodeTable};
+use crate::wide::OverflowPolicy;
+use std::collections::BTreeSet;
+use std::sync::mpsc::{Receiver, SyncSender};
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum StopReason {
+    Step,
+    Breakpoint(Word),
+    Watchpoint { ip: Word, address: Word, old: Word, new: Word },
+    Halted,
+    Fault(IntcodeError),
+}
+
+pub struct Debugger {
+    memory: PagedMemory,
+    state: ProcessorState,
+    breakpoints: BTreeSet<Word>,
+    watchpoints: BTreeSet<Word>,
+    opcodes: OpcodeTable,
+    halted: bool,
+}
+
+impl Debugger {
+    pub fn new(program: &Memory) -> Debugger {
+        // Watchpoints look at every store, so writes made by custom opcodes are seen too
+        let mut memory = load_program(program);
+        memory.record_writes();
+        return Debugger {
+            memory,
+            state: ProcessorState::new(),
+            breakpoints: BTreeSet::new(),
+            watchpoints: BTreeSet::new(),
+            opcodes: OpcodeTable::new(),
+            halted: false,
+        };
+    }
+
+    // Adds an opcode, false if the op code is built in or already registered
+    pub fn register_opcode(&mut self, opcode: CustomOpcode) -> bool {
+        return self.opcodes.register(opcode);
+    }
+
+    pub fn set_breakpoint(&mut self, address: Word) {
+        self.breakpoints.insert(address);
+    }
+
+    pub fn clear_breakpoint(&mut self, address: Word) {
+        self.breakpoints.remove(&address);
+    }
+
+    pub fn set_watchpoint(&mut self, address: Word) {
+        self.watchpoints.insert(address);
+    }
+
+    pub fn clear_watchpoint(&mut self, address: Word) {
+        self.watchpoints.remove(&address);
+    }
+
+    pub fn breakpoints(&self) -> Vec<Word> {
+        return self.breakpoints.iter().cloned().collect();
+    }
+
+    pub fn watchpoints(&self) -> Vec<Word> {
+        return self.watchpoints.iter().cloned().collect();
+    }
+
+    pub fn state(&self) -> ProcessorState {
+        return self.state;
+    }
+
+    pub fn set_state(&mut self, state: ProcessorState) {
+        self.state = state;
+    }
+
+    pub fn is_halted(&self) -> bool {
+        return self.halted;
+    }
+
+    // Addresses outside the memory read as 0
+    pub fn peek(&self, address: Word) -> Word {
+        return self.memory.load(address).unwrap_or(0);
+    }
+
+    // Returns false if the address is outside the memory
+    pub fn poke(&mut self, address: Word, value: Word) -> bool {
+        return self.memory.store(address, value);
+    }
+
+    pub fn memory(&self) -> &PagedMemory {
+        return &self.memory;
+    }
+
+    // Instruction at the current ip, None if it can not be decoded
+    pub fn current_instruction(&self) -> Option<Instruction> {
+        return try_decode_instruction(&self.state.ip, &self.memory);
+    }
+
+    // Executes a single instruction, breakpoints are ignored
+    pub fn step(&mut self, io: &mut dyn IO) -> StopReason {
+        if self.halted {
+            return StopReason::Halted;
+        }
+        let ip = self.state.ip;
+        // Drops the stores made by poke()
+        self.memory.take_writes();
+
+        match self.execute(io) {
+            Ok(true) => {
+                self.halted = true;
+                return StopReason::Halted;
+            },
+            Ok(false) => {},
+            Err(error) => return StopReason::Fault(error),
+        }
+        return match self.memory.take_writes().into_iter().find(|(a, _)| self.watchpoints.contains(a)) {
+            Some((address, old)) => StopReason::Watchpoint { ip, address, old, new: self.peek(address) },
+            None => StopReason::Step,
+        }
+    }
+
+    fn execute(&mut self, io: &mut dyn IO) -> Result<bool, IntcodeError> {
+        let opcode = match self.opcodes.get(word_at(&self.memory, self.state.ip)) {
+            Some(opcode) => opcode,
+            None => return execute_step(&mut self.memory, &mut self.state, io, OverflowPolicy::default()),
+        };
+        let ip = self.state.ip;
+        let params = opcode.decode(&ip, &self.memory)?;
+        return match (opcode.execute)(&params, &mut self.memory, &mut self.state, io)? {
+            Some(next) => {
+                self.state.ip = next;
+                Ok(false)
+            },
+            None => Ok(true),
+        }
+    }
+
+    // Runs until a breakpoint or watchpoint is hit, or the program halts or faults
+    pub fn resume(&mut self, io: &mut dyn IO) -> StopReason {
+        loop {
+            let reason = self.step(io);
+            if reason != StopReason::Step {
+                return reason;
+            }
+            if self.breakpoints.contains(&self.state.ip) {
+                return StopReason::Breakpoint(self.state.ip);
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum DebugCommand {
+    Step,
+    Continue,
+    SetBreakpoint(Word),
+    ClearBreakpoint(Word),
+    SetWatchpoint(Word),
+    ClearWatchpoint(Word),
+    State,
+    SetState(ProcessorState),
+    Peek(Word, Word),
+    Poke(Word, Word),
+    Quit,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum DebugEvent {
+    Stopped { reason: StopReason, state: ProcessorState, next: Option<Instruction> },
+    State(ProcessorState),
+    Memory(Word, Vec<Word>),
+    Done,
+}
+
+// Runs a debugger session, answering each command with one event. Program IO goes to io.
+pub fn serve(program: &Memory, commands: Receiver<DebugCommand>, events: SyncSender<DebugEvent>, io: &mut dyn IO) {
+    let mut debugger = Debugger::new(program);
+    loop {
+        let command = match commands.recv() {
+            Ok(command) => command,
+            Err(_) => return,
+        };
+        let event = match command {
+            DebugCommand::Step | DebugCommand::Continue => {
+                let reason = match command {
+                    DebugCommand::Step => debugger.step(io),
+                    _ => debugger.resume(io),
+                };
+                DebugEvent::Stopped { reason, state: debugger.state(), next: debugger.current_instruction() }
+            },
+            DebugCommand::SetBreakpoint(a) => {
+                debugger.set_breakpoint(a);
+                DebugEvent::Done
+            },
+            DebugCommand::ClearBreakpoint(a) => {
+                debugger.clear_breakpoint(a);
+                DebugEvent::Done
+            },
+            DebugCommand::SetWatchpoint(a) => {
+                debugger.set_watchpoint(a);
+                DebugEvent::Done
+            },
+            DebugCommand::ClearWatchpoint(a) => {
+                debugger.clear_watchpoint(a);
+                DebugEvent::Done
+            },
+            DebugCommand::State => DebugEvent::State(debugger.state()),
+            DebugCommand::SetState(state) => {
+                debugger.set_state(state);
+                DebugEvent::Done
+            },
+            DebugCommand::Peek(address, len) => {
+                let values = (address..address + len).map(|a| debugger.peek(a)).collect();
+                DebugEvent::Memory(address, values)
+            },
+            DebugCommand::Poke(address, value) => {
+                debugger.poke(address, value);
+                DebugEvent::Done
+            },
+            DebugCommand::Quit => {
+                events.send(DebugEvent::Done).unwrap();
+                return;
+            },
+        };
+        if events.send(event).is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::debugger::{Debugger, StopReason, DebugCommand, DebugEvent, serve};
+    use crate::intmachine::{BufferIO, IntcodeError, ProcessorState, Instruction, Parameter, load_parameter};
+    use crate::opcodes::{CustomOpcode, ParameterRule};
+    use crate::assembler::assemble;
+    use std::sync::mpsc;
+    use std::thread;
+
+    fn counter() -> Vec<i64> {
+        return assemble("
+        loop:   out  [count]
+                add  [count], 1, [count]
+                lt   [count], 3, [more]
+                jt   [more], loop
+                hlt
+        count:  data 0
+        more:   data 0
+        ").unwrap();
+    }
+
+    #[test]
+    fn test_step() {
+        let mut debugger = Debugger::new(&counter());
+        let mut io = BufferIO::new(vec![]);
+        assert_eq!(debugger.current_instruction(), Some(Instruction::Output { src: Parameter::Pos(14) }));
+        assert_eq!(debugger.step(&mut io), StopReason::Step);
+        assert_eq!(debugger.state(), ProcessorState { ip: 2, relative_base: 0 });
+        assert_eq!(io.output, vec![0]);
+    }
+
+    #[test]
+    fn test_breakpoint() {
+        let mut debugger = Debugger::new(&counter());
+        let mut io = BufferIO::new(vec![]);
+        debugger.set_breakpoint(0);
+        assert_eq!(debugger.resume(&mut io), StopReason::Breakpoint(0));
+        assert_eq!(io.output, vec![0]);
+        assert_eq!(debugger.resume(&mut io), StopReason::Breakpoint(0));
+        assert_eq!(io.output, vec![0, 1]);
+        debugger.clear_breakpoint(0);
+        assert_eq!(debugger.resume(&mut io), StopReason::Halted);
+        assert_eq!(io.output, vec![0, 1, 2]);
+        assert!(debugger.is_halted());
+        assert_eq!(debugger.step(&mut io), StopReason::Halted);
+    }
+
+    #[test]
+    fn test_fault() {
+        let mut debugger = Debugger::new(&vec![1101, 40, 2, 4, 0]);
+        let mut io = BufferIO::new(vec![]);
+        assert_eq!(debugger.resume(&mut io), StopReason::Fault(IntcodeError::UnknownInstruction { ip: 4, word: 42 }));
+        assert_eq!(debugger.state().ip, 4);
+        assert!(!debugger.is_halted());
+    }
+
+    #[test]
+    fn test_watchpoint_and_poke() {
+        let mut debugger = Debugger::new(&counter());
+        let mut io = BufferIO::new(vec![]);
+        debugger.set_watchpoint(14);
+        assert_eq!(debugger.resume(&mut io), StopReason::Watchpoint { ip: 2, address: 14, old: 0, new: 1 });
+        debugger.poke(14, 2);
+        debugger.clear_watchpoint(14);
+        assert_eq!(debugger.resume(&mut io), StopReason::Halted);
+        assert_eq!(io.output, vec![0, 2]);
+        assert_eq!(debugger.peek(14), 3);
+    }
+
+    #[test]
+    fn test_watchpoint_custom_opcode() {
+        // inc a adds 1 to the cell at address a through memory.store, it has no Write parameter
+        let inc = CustomOpcode::new(42, "inc", vec![ParameterRule::Read], |params, memory, state, _io| {
+            let address = load_parameter(memory, state, params[0])?;
+            let value = memory.load(address).unwrap_or(0);
+            memory.store(address, value + 1);
+            return Ok(Some(state.ip + 2));
+        });
+        let mut debugger = Debugger::new(&vec![142, 7, 42, 6, 99, 0, 7, 0]);
+        let mut io = BufferIO::new(vec![]);
+        assert!(debugger.register_opcode(inc));
+        debugger.set_watchpoint(7);
+        assert_eq!(debugger.resume(&mut io), StopReason::Watchpoint { ip: 0, address: 7, old: 0, new: 1 });
+        assert_eq!(debugger.resume(&mut io), StopReason::Watchpoint { ip: 2, address: 7, old: 1, new: 2 });
+        assert_eq!(debugger.resume(&mut io), StopReason::Halted);
+    }
+
+    #[test]
+    fn test_serve() {
+        let program = counter();
+        let (commands, command_receiver) = mpsc::channel();
+        let (event_sender, events) = mpsc::sync_channel(0);
+        let child = thread::spawn(move || {
+            let mut io = BufferIO::new(vec![]);
+            serve(&program, command_receiver, event_sender, &mut io);
+            return io.output;
+        });
+
+        commands.send(DebugCommand::SetBreakpoint(10)).unwrap();
+        assert_eq!(events.recv().unwrap(), DebugEvent::Done);
+        commands.send(DebugCommand::Continue).unwrap();
+        match events.recv().unwrap() {
+            DebugEvent::Stopped { reason, state, .. } => {
+                assert_eq!(reason, StopReason::Breakpoint(10));
+                assert_eq!(state.ip, 10);
+            },
+            e => panic!("Unexpected event: {:?}", e),
+        }
+        commands.send(DebugCommand::Peek(14, 2)).unwrap();
+        assert_eq!(events.recv().unwrap(), DebugEvent::Memory(14, vec![1, 1]));
+        commands.send(DebugCommand::Quit).unwrap();
+        assert_eq!(events.recv().unwrap(), DebugEvent::Done);
+        assert_eq!(child.join().unwrap(), vec![0]);
+    }
+}
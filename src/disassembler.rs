@@ -0,0 +1,260 @@
+// Disassembler producing listings in the assembler syntax.
+//
+// Code is found by following the control flow from address 0. Jumps with an immediate
+// target are followed and get a synthesized label, jumps through memory can not be
+// followed statically. Everything that is not reached is listed as data.
+
+use crate::intmachine::{Instruction, IsaLevel, Parameter, Memory, Word, try_decode_instruction};
+use crate::intmachine::Instruction::{Halt, JumpIfTrue, JumpIfFalse};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Entry {
+    Code { address: Word, instruction: Instruction },
+    Data { address: Word, value: Word },
+}
+
+impl Entry {
+    pub fn address(&self) -> Word {
+        return match self {
+            Entry::Code { address, .. } => *address,
+            Entry::Data { address, .. } => *address,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Listing {
+    pub entries: Vec<Entry>,
+    pub labels: BTreeMap<Word, String>,
+    memory: Memory,
+}
+
+pub fn disassemble(memory: &Memory) -> Listing {
+    return disassemble_from(memory, &[0]);
+}
+
+pub fn disassemble_from(memory: &Memory, entry_points: &[Word]) -> Listing {
+    let mut starts: HashSet<Word> = HashSet::new();
+    let mut covered: HashSet<Word> = HashSet::new();
+    let mut labels = BTreeMap::new();
+    let mut work: Vec<Word> = entry_points.to_vec();
+
+    // Return addresses are only ever stored as constants, so the instruction after an
+    // unconditional jump is code if that address shows up as an immediate operand
+    let mut after_jumps: Vec<Word> = vec![];
+    let mut constants: HashSet<Word> = HashSet::new();
+
+    loop {
+        while let Some(address) = work.pop() {
+            if starts.contains(&address) || covered.contains(&address) {
+                continue;
+            }
+            let instruction = match decode(memory, address) {
+                Some(instruction) => instruction,
+                None => continue,
+            };
+            let size = instruction.size();
+            if (address..address + size).any(|a| covered.contains(&a)) {
+                // Would overlap an instruction already decoded
+                continue;
+            }
+            starts.insert(address);
+            covered.extend(address..address + size);
+            for p in instruction.params() {
+                if let Parameter::Imm(v) = p {
+                    constants.insert(v);
+                }
+            }
+
+            let next = address + size;
+            match instruction {
+                Halt => {},
+                JumpIfTrue { cond, target } | JumpIfFalse { cond, target } => {
+                    let jump_on_true = matches!(instruction, JumpIfTrue { .. });
+                    let (may_jump, may_continue) = match cond {
+                        Parameter::Imm(v) => ((v != 0) == jump_on_true, (v != 0) != jump_on_true),
+                        _ => (true, true),
+                    };
+                    if may_jump {
+                        if let Parameter::Imm(t) = target {
+                            labels.insert(t, format!("l{}", t));
+                            work.push(t);
+                        }
+                    }
+                    if may_continue {
+                        work.push(next);
+                    } else {
+                        after_jumps.push(next);
+                    }
+                },
+                _ => work.push(next),
+            }
+        }
+
+        let (returns, rest): (Vec<Word>, Vec<Word>) = after_jumps.iter()
+            .partition(|a| constants.contains(a));
+        if returns.is_empty() {
+            break;
+        }
+        after_jumps = rest;
+        for address in returns {
+            labels.insert(address, format!("l{}", address));
+            work.push(address);
+        }
+    }
+
+    let mut entries = vec![];
+    let mut address = 0;
+    while (address as usize) < memory.len() {
+        if starts.contains(&address) {
+            let instruction = decode(memory, address).unwrap();
+            entries.push(Entry::Code { address, instruction });
+            address += instruction.size();
+        } else {
+            entries.push(Entry::Data { address, value: memory[address as usize] });
+            address += 1;
+        }
+    }
+    // Only keep labels that point at the start of an entry
+    let starts: HashSet<Word> = entries.iter().map(|e| e.address()).collect();
+    labels.retain(|a, _| starts.contains(a));
+
+    return Listing { entries, labels, memory: memory.clone() };
+}
+
+// Only accept words that encode back to themselves, so listings reassemble to the same image
+fn decode(memory: &Memory, address: Word) -> Option<Instruction> {
+    let instruction = try_decode_instruction(&address, memory)?;
+    if instruction.encode()[0] != memory[address as usize] {
+        return None;
+    }
+    return Some(instruction);
+}
+
+impl Listing {
+    // Addresses of decoded instructions that are not part of the given instruction set
+    pub fn isa_violations(&self, level: IsaLevel) -> Vec<Word> {
+        return self.entries.iter().filter_map(|e| match e {
+            Entry::Code { address, instruction } if !level.allows(instruction) => Some(*address),
+            _ => None,
+        }).collect();
+    }
+
+    fn format_instruction(&self, instruction: &Instruction) -> String {
+        let params = instruction.params();
+        let jump_target = match instruction {
+            JumpIfTrue { .. } | JumpIfFalse { .. } => Some(1),
+            _ => None,
+        };
+        let formatted: Vec<String> = params.iter().enumerate().map(|(i, p)| {
+            match (p, self.labels.get(&p.value())) {
+                (Parameter::Imm(_), Some(label)) if Some(i) == jump_target => label.clone(),
+                _ => p.to_string(),
+            }
+        }).collect();
+        if formatted.is_empty() {
+            return instruction.mnemonic().to_string();
+        }
+        return format!("{:<4} {}", instruction.mnemonic(), formatted.join(", "));
+    }
+}
+
+impl fmt::Display for Listing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for entry in self.entries.iter() {
+            let address = entry.address();
+            let label = match self.labels.get(&address) {
+                Some(label) => format!("{}:", label),
+                None => String::new(),
+            };
+            let (text, size) = match entry {
+                Entry::Code { instruction, .. } => (self.format_instruction(instruction), instruction.size()),
+                Entry::Data { value, .. } => (format!("data {}", value), 1),
+            };
+            let words: Vec<String> = (address..address + size)
+                .map(|a| self.memory[a as usize].to_string())
+                .collect();
+            writeln!(f, "{:<8}{:<32}; {:04}  {}", label, text, address, words.join(","))?;
+        }
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::disassembler::{disassemble, Entry};
+    use crate::assembler::assemble;
+    use crate::intmachine::{read_program, Instruction, IsaLevel, Parameter};
+
+    #[test]
+    fn test_code_and_data() {
+        let program = vec![3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8];
+        let listing = disassemble(&program);
+        assert_eq!(listing.entries.len(), 6);
+        assert_eq!(listing.entries[0], Entry::Code {
+            address: 0,
+            instruction: Instruction::Input { dst: Parameter::Pos(9) },
+        });
+        assert_eq!(listing.entries[4], Entry::Data { address: 9, value: -1 });
+        assert_eq!(listing.entries[5], Entry::Data { address: 10, value: 8 });
+    }
+
+    #[test]
+    fn test_labels() {
+        let program = assemble("
+                jf  [rb], done
+                out 1
+        done:   hlt
+        ").unwrap();
+        let listing = disassemble(&program);
+        assert_eq!(listing.labels.get(&5), Some(&"l5".to_string()));
+        assert!(listing.to_string().contains("jf   [rb+0], l5"));
+    }
+
+    #[test]
+    fn test_return_address() {
+        let program = assemble("
+                add  back, 0, [rb]
+                jt   1, func
+        back:   hlt
+                data 7
+        func:   jt   1, [rb]
+        ").unwrap();
+        let listing = disassemble(&program);
+        assert_eq!(listing.entries[2], Entry::Code { address: 7, instruction: Instruction::Halt });
+        assert_eq!(listing.entries[3], Entry::Data { address: 8, value: 7 });
+        assert_eq!(listing.labels.get(&7), Some(&"l7".to_string()));
+    }
+
+    #[test]
+    fn test_invalid_words() {
+        // Unknown op code, invalid parameter mode and a truncated instruction
+        let program = vec![1105, 1, 4, 42, 99, 33, 301, 1];
+        let listing = disassemble(&program);
+        assert_eq!(listing.entries[1], Entry::Data { address: 3, value: 42 });
+        assert_eq!(listing.entries.iter().filter(|e| match e { Entry::Code { .. } => true, _ => false }).count(), 2);
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        for filename in ["data/day21/input.txt", "data/day25/input.txt"].iter() {
+            let program = read_program(filename);
+            let listing = disassemble(&program);
+            assert_eq!(assemble(&listing.to_string()).unwrap(), program);
+        }
+    }
+
+    #[test]
+    fn test_isa_violations() {
+        let day02 = disassemble(&read_program("data/day02/input.txt"));
+        let day05 = disassemble(&read_program("data/day05/input.txt"));
+        let day09 = disassemble(&read_program("data/day09/input.txt"));
+        assert_eq!(day02.isa_violations(IsaLevel::Day02), vec![]);
+        assert!(!day05.isa_violations(IsaLevel::Day02).is_empty());
+        assert_eq!(day05.isa_violations(IsaLevel::Day05), vec![]);
+        assert!(!day09.isa_violations(IsaLevel::Day05).is_empty());
+        assert_eq!(day09.isa_violations(IsaLevel::Day09), vec![]);
+    }
+}
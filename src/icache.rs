@@ -0,0 +1,186 @@
+// Decoded instruction cache, see intmachine::execute_cached.
+//
+// Instructions are decoded once per address and reused until a write hits one of their words.
+// Only addresses below the cache size are cached, the rest are decoded every time.
+//
+// One cache can be shared by many runs of the same image. Every run starts from the image
+// again, so prepare() drops what the writes of the previous run had changed.
+
+use crate::intmachine::{decode_instruction, Instruction, IntcodeError, Memory, PagedMemory, Word};
+use std::collections::BTreeSet;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct InstructionCache {
+    entries: Vec<Option<Instruction>>,
+    // The image the entries were decoded from
+    image: Memory,
+    // Cached addresses written since the last prepare(), once each
+    written: Vec<bool>,
+    dirty: Vec<Word>,
+    // Written addresses past the cache that the last cached instructions reach into
+    dirty_beyond: BTreeSet<Word>,
+    hits: u64,
+    misses: u64,
+}
+
+// Longest instruction, an op code and three parameters
+const MAX_SIZE: Word = 4;
+
+impl InstructionCache {
+    pub fn new(size: usize) -> InstructionCache {
+        return InstructionCache { entries: vec![None; size], image: vec![], written: vec![false; size], dirty: vec![], dirty_beyond: BTreeSet::new(), hits: 0, misses: 0 };
+    }
+
+    // Called before a run of image, everything is dropped if it is not the image of the last run
+    pub fn prepare(&mut self, image: &Memory) {
+        if self.image != *image {
+            self.image = image.clone();
+            self.entries.iter_mut().for_each(|e| *e = None);
+            self.written.iter_mut().for_each(|w| *w = false);
+            self.dirty.clear();
+            self.dirty_beyond.clear();
+            return;
+        }
+        for address in std::mem::take(&mut self.dirty) {
+            self.written[address as usize] = false;
+            self.drop_entries(address);
+        }
+        for address in std::mem::take(&mut self.dirty_beyond) {
+            self.drop_entries(address);
+        }
+    }
+
+    pub fn decode(&mut self, ip: &Word, memory: &PagedMemory) -> Result<Instruction, IntcodeError> {
+        let index = *ip as usize;
+        if *ip < 0 || index >= self.entries.len() {
+            return decode_instruction(ip, memory);
+        }
+        if let Some(instruction) = self.entries[index] {
+            self.hits += 1;
+            return Ok(instruction);
+        }
+        self.misses += 1;
+        let instruction = decode_instruction(ip, memory)?;
+        self.entries[index] = Some(instruction);
+        return Ok(instruction);
+    }
+
+    // Called after a write to address, drops every cached instruction that has a word there
+    pub fn invalidate(&mut self, address: Word) {
+        let size = self.written.len() as Word;
+        if address >= 0 && address < size && !self.written[address as usize] {
+            self.written[address as usize] = true;
+            self.dirty.push(address);
+        } else if address >= size && address < size + MAX_SIZE - 1 {
+            self.dirty_beyond.insert(address);
+        }
+        self.drop_entries(address);
+    }
+
+    fn drop_entries(&mut self, address: Word) {
+        for start in address - MAX_SIZE + 1..=address {
+            if start < 0 || start as usize >= self.entries.len() {
+                continue;
+            }
+            if let Some(instruction) = self.entries[start as usize] {
+                if start + instruction.size() > address {
+                    self.entries[start as usize] = None;
+                }
+            }
+        }
+    }
+
+    pub fn hits(&self) -> u64 {
+        return self.hits;
+    }
+
+    pub fn misses(&self) -> u64 {
+        return self.misses;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::icache::InstructionCache;
+    use crate::intmachine::{PagedMemory, Instruction, Parameter, execute_with_result, execute_with_result_cached, read_program, DEFAULT_MEMORY_LIMIT};
+    use crate::assembler::assemble;
+
+    #[test]
+    fn test_invalidate() {
+        let mut memory = PagedMemory::from_image(&vec![1101, 1, 2, 7, 104, 5, 99, 0], DEFAULT_MEMORY_LIMIT);
+        let mut cache = InstructionCache::new(8);
+        cache.decode(&0, &memory).unwrap();
+        cache.decode(&4, &memory).unwrap();
+        assert_eq!(cache.decode(&4, &memory), Ok(Instruction::Output { src: Parameter::Imm(5) }));
+        assert_eq!((cache.hits(), cache.misses()), (1, 2));
+
+        // Only the instruction covering the address is dropped
+        memory.store(5, 6);
+        cache.invalidate(5);
+        assert_eq!(cache.decode(&4, &memory), Ok(Instruction::Output { src: Parameter::Imm(6) }));
+        cache.decode(&0, &memory).unwrap();
+        assert_eq!((cache.hits(), cache.misses()), (2, 3));
+
+        // Uncached addresses are still decoded
+        memory.store(100, 99);
+        assert_eq!(cache.decode(&100, &memory), Ok(Instruction::Halt));
+        assert_eq!((cache.hits(), cache.misses()), (2, 3));
+    }
+
+    #[test]
+    fn test_same_result() {
+        // Writes into its own code, see intmachine test_jumps
+        let program = vec![3,3,1105,-1,9,1101,0,0,12,4,12,99,1];
+        let mut cache = InstructionCache::new(program.len());
+        for input in [0, 9, 0].iter() {
+            assert_eq!(execute_with_result_cached(&program, vec![*input], &mut cache), execute_with_result(&program, vec![*input]));
+        }
+        let program = read_program("data/day19/input.txt");
+        let mut cache = InstructionCache::new(program.len());
+        for (x, y) in [(0, 0), (10, 12), (30, 40)].iter() {
+            assert_eq!(execute_with_result_cached(&program, vec![*x, *y], &mut cache), execute_with_result(&program, vec![*x, *y]));
+        }
+        // Later runs only decode again what earlier runs wrote over
+        let misses = cache.misses();
+        execute_with_result_cached(&program, vec![10, 12], &mut cache).unwrap();
+        let mut fresh = InstructionCache::new(program.len());
+        execute_with_result_cached(&program, vec![10, 12], &mut fresh).unwrap();
+        assert!(cache.misses() - misses < fresh.misses() / 4);
+    }
+
+    #[test]
+    fn test_write_beyond_image() {
+        // The last out reads its parameter from past the image, the run with input 1 writes it.
+        // add 0, 99, [13]  in [30]  jf [30], 11  in [12]  out 0
+        let program = vec![1101,0,99,13,3,30,1006,30,11,3,12,104];
+        let mut cache = InstructionCache::new(program.len());
+        for input in [vec![1, 7], vec![0], vec![1, 8], vec![0]].iter() {
+            assert_eq!(execute_with_result_cached(&program, input.clone(), &mut cache), execute_with_result(&program, input.clone()));
+        }
+        assert_eq!(execute_with_result_cached(&program, vec![0], &mut cache), Ok(vec![0]));
+    }
+
+    #[test]
+    fn test_shared() {
+        // Input 1 turns the out into an immediate one, a later run with 0 starts from the image again
+        let program = assemble("
+                in   [flag]
+                jf   [flag], print
+                add  0, 104, [print]
+        print:  out  [value]
+                hlt
+        flag:   data 0
+        value:  data 5
+        ").unwrap();
+        let mut cache = InstructionCache::new(program.len());
+        for input in [1, 0, 1, 0].iter() {
+            assert_eq!(execute_with_result_cached(&program, vec![*input], &mut cache), execute_with_result(&program, vec![*input]));
+        }
+        assert_eq!(execute_with_result_cached(&program, vec![0], &mut cache), Ok(vec![5]));
+
+        // Another image does not reuse the entries
+        let other = vec![104, 1, 99];
+        assert_eq!(execute_with_result_cached(&other, vec![], &mut cache), Ok(vec![1]));
+        assert_eq!(execute_with_result_cached(&program, vec![0], &mut cache), Ok(vec![5]));
+    }
+}
@@ -1,35 +1,366 @@
 use Instruction::{Halt, Add, Multiply, Input, Output, JumpIfTrue, JumpIfFalse, LessThan, Equals, AdjustRelativeBase};
 use Parameter::{Imm, Pos, Rel};
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 use std::sync::mpsc::{Receiver, SyncSender};
-use std::sync::mpsc;
-use std::thread;
 use std::io::{Write, stdout};
 use std::thread::sleep;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+use std::fmt;
+use std::fs;
+use std::io;
+use crate::profiler::Profile;
+use crate::trace::{Trace, traced_step, put, get};
+use crate::opcodes::{CustomOpcode, OpcodeTable, ParameterRule};
+use crate::selfmod::CodeTracker;
+use crate::icache::InstructionCache;
+use crate::wide::{OverflowPolicy, WordType};
 use crate::intmachine::Message::RequestInput;
 
 pub type Word = i64;
 pub type Memory = Vec<Word>;
 type OutputData = Vec<Word>;
 
-#[derive(Debug)]
-pub enum Message {
+pub const PAGE_SIZE: Word = 4096;
+pub const DEFAULT_MEMORY_LIMIT: Word = 1 << 32;
+
+// Anything instructions can be decoded from, None means the address does not exist
+pub trait Addressable {
+    fn read(&self, address: Word) -> Option<Word>;
+}
+
+impl Addressable for Memory {
+    fn read(&self, address: Word) -> Option<Word> {
+        if address < 0 {
+            return None;
+        }
+        return self.get(address as usize).cloned();
+    }
+}
+
+// Machine memory. Pages are allocated when first written, so only the limit bounds the
+// addresses a program can use. Unwritten cells read as 0. Generic over the word, see WordType.
+#[derive(Clone, Debug)]
+pub struct PagedMemory<W = Word> {
+    pages: HashMap<Word, Vec<W>>,
+    limit: Word,
+    // Address and previous value of each store, while recording, see record_writes()
+    writes: Option<Vec<(Word, W)>>,
+}
+
+// Not derived, the write log is not part of the contents
+impl <W: PartialEq> PartialEq for PagedMemory<W> {
+    fn eq(&self, other: &PagedMemory<W>) -> bool {
+        return self.limit == other.limit && self.pages == other.pages;
+    }
+}
+
+impl <W: Eq> Eq for PagedMemory<W> {}
+
+impl PagedMemory {
+    pub fn new(limit: Word) -> PagedMemory {
+        return PagedMemory::empty(limit);
+    }
+
+    pub fn from_image(image: &Memory, limit: Word) -> PagedMemory {
+        return PagedMemory::from_words(image, limit);
+    }
+}
+
+impl <W: Clone + From<i64>> PagedMemory<W> {
+    pub fn empty(limit: Word) -> PagedMemory<W> {
+        return PagedMemory { pages: HashMap::new(), limit, writes: None };
+    }
+
+    pub fn from_words(image: &[W], limit: Word) -> PagedMemory<W> {
+        let mut memory = PagedMemory::empty(limit);
+        for (page, chunk) in image.chunks(PAGE_SIZE as usize).enumerate() {
+            let mut words = chunk.to_vec();
+            words.resize(PAGE_SIZE as usize, W::from(0));
+            memory.pages.insert(page as Word, words);
+        }
+        return memory;
+    }
+
+    pub fn limit(&self) -> Word {
+        return self.limit;
+    }
+
+    pub fn allocated_pages(&self) -> usize {
+        return self.pages.len();
+    }
+
+    pub fn load(&self, address: Word) -> Option<W> {
+        if address < 0 || address >= self.limit {
+            return None;
+        }
+        return match self.pages.get(&(address / PAGE_SIZE)) {
+            Some(page) => Some(page[(address % PAGE_SIZE) as usize].clone()),
+            None => Some(W::from(0)),
+        }
+    }
+
+    // Returns false if the address is outside the memory
+    pub fn store(&mut self, address: Word, value: W) -> bool {
+        if address < 0 || address >= self.limit {
+            return false;
+        }
+        let page = self.pages.entry(address / PAGE_SIZE).or_insert_with(|| vec![W::from(0); PAGE_SIZE as usize]);
+        let old = std::mem::replace(&mut page[(address % PAGE_SIZE) as usize], value);
+        if let Some(writes) = self.writes.as_mut() {
+            writes.push((address, old));
+        }
+        return true;
+    }
+
+    // Starts logging every store, whoever makes it, until take_writes() is called
+    pub fn record_writes(&mut self) {
+        if self.writes.is_none() {
+            self.writes = Some(vec![]);
+        }
+    }
+
+    // Address and previous value of the stores since the last call, oldest first
+    pub fn take_writes(&mut self) -> Vec<(Word, W)> {
+        return match self.writes.as_mut() {
+            Some(writes) => std::mem::take(writes),
+            None => vec![],
+        }
+    }
+
+    // Flat copy up to the end of the highest allocated page
+    pub fn to_vec(&self) -> Vec<W> {
+        let len = match self.pages.keys().max() {
+            Some(page) => Word::min((page + 1) * PAGE_SIZE, self.limit),
+            None => 0,
+        };
+        return (0..len).map(|a| self.load(a).unwrap()).collect();
+    }
+}
+
+impl Addressable for PagedMemory {
+    fn read(&self, address: Word) -> Option<Word> {
+        return self.load(address);
+    }
+}
+
+// Decoding view of memory of any word type, values that do not fit a Word read as 0.
+// check_params() rejects the parameters that were cut that way.
+pub(crate) struct WordView<'a, W>(pub &'a PagedMemory<W>);
+
+impl <'a, W: WordType> Addressable for WordView<'a, W> {
+    fn read(&self, address: Word) -> Option<Word> {
+        return self.0.load(address).map(|v| v.to_word().unwrap_or(0));
+    }
+}
+
+// Fails with Overflow if a parameter of the instruction at ip was cut by WordView. Cut immediates
+// can be allowed, the engine loads immediates from memory with WordType::immediate.
+pub(crate) fn check_params<W: WordType>(ip: &Word, params: &[Parameter], memory: &PagedMemory<W>, cut_immediates: bool) -> Result<(), IntcodeError> {
+    for (i, param) in params.iter().enumerate() {
+        if cut_immediates && param.mode() == 1 {
+            continue;
+        }
+        if memory.load(ip + 1 + i as Word).and_then(|v| v.to_word()).is_none() {
+            return Err(IntcodeError::Overflow { ip: *ip, word: word_at(memory, *ip) });
+        }
+    }
+    return Ok(());
+}
+
+// The value at address as a Word, 0 if it is missing or does not fit
+pub(crate) fn word_at<W: WordType>(memory: &PagedMemory<W>, address: Word) -> Word {
+    return memory.load(address).and_then(|v| v.to_word()).unwrap_or(0);
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Message<W = Word> {
     Shutdown,
-    Data(Word),
+    Data(W),
     RequestInput,
 }
 
+impl <W: WordType> Message<W> {
+    // For error reports, data that does not fit a Word becomes 0
+    pub(crate) fn to_word(&self) -> Message {
+        return match self {
+            Message::Shutdown => Message::Shutdown,
+            Message::Data(data) => Message::Data(data.to_word().unwrap_or(0)),
+            Message::RequestInput => Message::RequestInput,
+        }
+    }
+}
+
+// Faults while executing, with the ip and word of the faulting instruction
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum IntcodeError {
+    UnknownInstruction { ip: Word, word: Word },
+    InvalidParameterMode { ip: Word, word: Word },
+    WriteToImmediate { ip: Word, word: Word },
+    AddressOutOfRange { ip: Word, word: Word, address: Word },
+    UnexpectedInput { ip: Word, word: Word, message: Message },
+    OutsideIsa { ip: Word, word: Word, level: IsaLevel },
+    Overflow { ip: Word, word: Word },
+    // A run stopped by Limits, before executing the instruction at state.ip
+    LimitExceeded { word: Word, limit: Limit, state: ProcessorState },
+}
+
+impl IntcodeError {
+    pub fn ip(&self) -> Word {
+        return match *self {
+            IntcodeError::UnknownInstruction { ip, .. } => ip,
+            IntcodeError::InvalidParameterMode { ip, .. } => ip,
+            IntcodeError::WriteToImmediate { ip, .. } => ip,
+            IntcodeError::AddressOutOfRange { ip, .. } => ip,
+            IntcodeError::UnexpectedInput { ip, .. } => ip,
+            IntcodeError::OutsideIsa { ip, .. } => ip,
+            IntcodeError::Overflow { ip, .. } => ip,
+            IntcodeError::LimitExceeded { state, .. } => state.ip,
+        }
+    }
+
+    pub fn word(&self) -> Word {
+        return match *self {
+            IntcodeError::UnknownInstruction { word, .. } => word,
+            IntcodeError::InvalidParameterMode { word, .. } => word,
+            IntcodeError::WriteToImmediate { word, .. } => word,
+            IntcodeError::AddressOutOfRange { word, .. } => word,
+            IntcodeError::UnexpectedInput { word, .. } => word,
+            IntcodeError::OutsideIsa { word, .. } => word,
+            IntcodeError::Overflow { word, .. } => word,
+            IntcodeError::LimitExceeded { word, .. } => word,
+        }
+    }
+}
 
+impl fmt::Display for IntcodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IntcodeError::UnknownInstruction { ip, word } =>
+                write!(f, "Unknown instruction {} at {}", word, ip),
+            IntcodeError::InvalidParameterMode { ip, word } =>
+                write!(f, "Invalid parameter mode in {} at {}", word, ip),
+            IntcodeError::WriteToImmediate { ip, word } =>
+                write!(f, "Writing to immediate parameter in {} at {}", word, ip),
+            IntcodeError::AddressOutOfRange { ip, word, address } =>
+                write!(f, "Address {} out of range in {} at {}", address, word, ip),
+            IntcodeError::UnexpectedInput { ip, word, message } =>
+                write!(f, "Expected input data, got {:?} in {} at {}", message, word, ip),
+            IntcodeError::OutsideIsa { ip, word, level } =>
+                write!(f, "Instruction {} at {} is not part of the {:?} instruction set", word, ip, level),
+            IntcodeError::Overflow { ip, word } =>
+                write!(f, "Overflow in {} at {}", word, ip),
+            IntcodeError::LimitExceeded { word, limit, state } =>
+                write!(f, "{} exceeded before {} at {}, relative base {}", limit, word, state.ip, state.relative_base),
+        }
+    }
+}
+
+impl std::error::Error for IntcodeError {}
+
+// Bounds for execute_with_limits and IntMachine::set_limits, None means unlimited
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct Limits {
+    pub max_instructions: Option<u64>,
+    pub max_outputs: Option<u64>,
+    pub max_time: Option<Duration>,
+    // Not a bound, but set with the others: what an add or mul that overflows does
+    pub overflow: OverflowPolicy,
+}
+
+impl Limits {
+    pub fn is_bounded(&self) -> bool {
+        return self.max_instructions.is_some() || self.max_outputs.is_some() || self.max_time.is_some();
+    }
+}
+
+// The limit that stopped a run
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
-struct ProcessorState {
-    ip :Word,
-    relative_base: Word,
+pub enum Limit {
+    Instructions(u64),
+    Outputs(u64),
+    Time(Duration),
 }
 
-pub trait IO {
-    fn send(&mut self, message: Message) -> ();
-    fn receive(&mut self) -> Message;
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            Limit::Instructions(n) => write!(f, "Limit of {} instructions", n),
+            Limit::Outputs(n) => write!(f, "Limit of {} outputs", n),
+            Limit::Time(t) => write!(f, "Time limit of {:?}", t),
+        }
+    }
+}
+
+// The instruction set as it grew over the puzzles
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum IsaLevel {
+    // add, mul and hlt, position parameters only
+    Day02,
+    // in, out, jumps, compares and immediate parameters
+    Day05,
+    // arb and relative parameters
+    Day09,
+}
+
+impl IsaLevel {
+    pub fn allows(&self, instruction: &Instruction) -> bool {
+        if *self == IsaLevel::Day09 {
+            return true;
+        }
+        let params = instruction.params();
+        return match self {
+            IsaLevel::Day02 => match instruction {
+                Add { .. } | Multiply { .. } | Halt => params.iter().all(|p| p.mode() == 0),
+                _ => false,
+            },
+            IsaLevel::Day05 => match instruction {
+                AdjustRelativeBase { .. } => false,
+                _ => params.iter().all(|p| p.mode() != 2),
+            },
+            IsaLevel::Day09 => true,
+        }
+    }
+
+    // Custom opcodes extend the full instruction set only
+    pub fn allows_custom(&self) -> bool {
+        return *self == IsaLevel::Day09;
+    }
+
+    // Decodes the instruction at ip, failing if it is not part of this level
+    pub fn decode<M: Addressable + ?Sized>(&self, ip: &Word, memory: &M) -> Result<Instruction, IntcodeError> {
+        let instruction = decode_instruction(ip, memory)?;
+        if !self.allows(&instruction) {
+            return Err(IntcodeError::OutsideIsa { ip: *ip, word: memory.read(*ip).unwrap_or(0), level: *self });
+        }
+        return Ok(instruction);
+    }
+}
+
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct ProcessorState {
+    pub ip :Word,
+    pub relative_base: Word,
+}
+
+impl ProcessorState {
+    pub fn new() -> ProcessorState {
+        return ProcessorState { ip: 0, relative_base: 0 };
+    }
+
+    // Effective address of a parameter, None for immediate parameters
+    pub fn address(&self, param: Parameter) -> Option<Word> {
+        return match param {
+            Pos(p) => Some(p),
+            Imm(_) => None,
+            Rel(p) => Some(self.relative_base + p),
+        }
+    }
+}
+
+pub trait IO<W = Word> {
+    fn send(&mut self, message: Message<W>) -> ();
+    fn receive(&mut self) -> Message<W>;
 }
 
 pub struct StandardIO {
@@ -47,14 +378,395 @@ impl IO for StandardIO {
     }
 }
 
-struct IntMachine {
-    memory: Memory,
+// IO without threads, input is taken from a queue and output collected
+pub struct BufferIO {
+    pub input: VecDeque<Word>,
+    pub output: Vec<Word>,
+}
+
+impl BufferIO {
+    pub fn new(input: Vec<Word>) -> BufferIO {
+        return BufferIO { input: VecDeque::from(input), output: vec![] };
+    }
+}
+
+impl IO for BufferIO {
+    fn send(&mut self, message: Message) {
+        if let Message::Data(data) = message {
+            self.output.push(data);
+        }
+    }
+
+    fn receive(&mut self) -> Message {
+        // Running out of input shows up as an UnexpectedInput error
+        return match self.input.pop_front() {
+            Some(data) => Message::Data(data),
+            None => Message::Shutdown,
+        }
+    }
+}
+
+// What a machine stopped for in IntMachine::run
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Status<W = Word> {
+    NeedsInput,
+    Output(W),
+    Halted,
+}
+
+// Single threaded machine, driven by calling run() until it needs input or halts.
+// Cloning forks the machine, so branches can be explored from the same point.
+// Values are Words unless another WordType is chosen, like i128 or BigInt.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct IntMachine<W = Word> {
+    memory: PagedMemory<W>,
     state: ProcessorState,
-    io: dyn IO,
+    input: VecDeque<W>,
+    output: VecDeque<W>,
+    halted: bool,
+    isa: IsaLevel,
+    // Also holds the overflow policy
+    limits: Limits,
+    // Counted against the limits since set_limits(), only while there are any
+    instructions: u64,
+    outputs: u64,
+    elapsed: Duration,
+    opcodes: OpcodeTable<W>,
+    tracker: Option<CodeTracker<W>>,
+}
+
+// Feeds input from the machine queue and collects output, custom opcodes may send more than one word
+struct MachineIO<'a, W> {
+    input: &'a mut VecDeque<W>,
+    output: &'a mut VecDeque<W>,
+}
+
+impl <'a, W> IO<W> for MachineIO<'a, W> {
+    fn send(&mut self, message: Message<W>) {
+        if let Message::Data(data) = message {
+            self.output.push_back(data);
+        }
+    }
+
+    fn receive(&mut self) -> Message<W> {
+        return match self.input.pop_front() {
+            Some(data) => Message::Data(data),
+            None => Message::Shutdown,
+        }
+    }
+}
+
+impl IntMachine {
+    pub fn new(program: &Memory) -> IntMachine {
+        return IntMachine::with_memory_limit(program, DEFAULT_MEMORY_LIMIT);
+    }
+
+    pub fn with_memory_limit(program: &Memory, limit: Word) -> IntMachine {
+        return IntMachine::from_memory(PagedMemory::from_image(program, limit));
+    }
+
+    pub fn save(&self, filename: &str) -> io::Result<()> {
+        return fs::write(filename, self.to_bytes());
+    }
+
+    // Custom opcodes, the code tracker, the limits and the overflow policy are not part of the snapshot
+    pub fn load(filename: &str) -> io::Result<IntMachine> {
+        let bytes = fs::read(filename)?;
+        return IntMachine::from_bytes(&bytes).ok_or(io::Error::new(io::ErrorKind::InvalidData, "Invalid snapshot file"));
+    }
+
+    // Snapshot layout, all varints: limit, ip, relative base, halted, isa, pending input and output, then the pages
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = b"ICS1".to_vec();
+        put(&mut out, self.memory.limit);
+        put(&mut out, self.state.ip);
+        put(&mut out, self.state.relative_base);
+        put(&mut out, self.halted as Word);
+        put(&mut out, self.isa as Word);
+        put(&mut out, self.input.len() as Word);
+        for v in self.input.iter() {
+            put(&mut out, *v);
+        }
+        put(&mut out, self.output.len() as Word);
+        for v in self.output.iter() {
+            put(&mut out, *v);
+        }
+        let mut pages: Vec<&Word> = self.memory.pages.keys().collect();
+        pages.sort();
+        put(&mut out, pages.len() as Word);
+        for page in pages {
+            put(&mut out, *page);
+            for v in self.memory.pages[page].iter() {
+                put(&mut out, *v);
+            }
+        }
+        return out;
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<IntMachine> {
+        if bytes.len() < 4 || &bytes[..4] != b"ICS1" {
+            return None;
+        }
+        let mut pos = 4;
+        let mut memory = PagedMemory::new(get(bytes, &mut pos)?);
+        let state = ProcessorState { ip: get(bytes, &mut pos)?, relative_base: get(bytes, &mut pos)? };
+        let halted = get(bytes, &mut pos)? != 0;
+        let isa = match get(bytes, &mut pos)? {
+            0 => IsaLevel::Day02,
+            1 => IsaLevel::Day05,
+            2 => IsaLevel::Day09,
+            _ => return None,
+        };
+        let mut input = VecDeque::new();
+        for _ in 0..get(bytes, &mut pos)? {
+            input.push_back(get(bytes, &mut pos)?);
+        }
+        let mut output = VecDeque::new();
+        for _ in 0..get(bytes, &mut pos)? {
+            output.push_back(get(bytes, &mut pos)?);
+        }
+        for _ in 0..get(bytes, &mut pos)? {
+            let page = get(bytes, &mut pos)?;
+            let mut words = Vec::with_capacity(PAGE_SIZE as usize);
+            for _ in 0..PAGE_SIZE {
+                words.push(get(bytes, &mut pos)?);
+            }
+            memory.pages.insert(page, words);
+        }
+        if pos != bytes.len() {
+            return None;
+        }
+        let mut machine = IntMachine::from_memory(memory);
+        machine.state = state;
+        machine.input = input;
+        machine.output = output;
+        machine.halted = halted;
+        machine.isa = isa;
+        return Some(machine);
+    }
+}
+
+impl <W: WordType> IntMachine<W> {
+    // Any word type, see WordType
+    pub fn from_words(program: &[W]) -> IntMachine<W> {
+        return IntMachine::from_memory(PagedMemory::from_words(program, DEFAULT_MEMORY_LIMIT));
+    }
+
+    // Loads an ordinary program into a machine of another word type
+    pub fn from_program(program: &Memory) -> IntMachine<W> {
+        let words: Vec<W> = program.iter().map(|v| W::from(*v)).collect();
+        return IntMachine::from_words(&words);
+    }
+
+    fn from_memory(memory: PagedMemory<W>) -> IntMachine<W> {
+        return IntMachine {
+            memory,
+            state: ProcessorState::new(),
+            input: VecDeque::new(),
+            output: VecDeque::new(),
+            halted: false,
+            isa: IsaLevel::Day09,
+            limits: Limits::default(),
+            instructions: 0,
+            outputs: 0,
+            elapsed: Duration::from_secs(0),
+            opcodes: OpcodeTable::new(),
+            tracker: None,
+        };
+    }
+
+    pub fn push_input(&mut self, value: W) {
+        self.input.push_back(value);
+    }
+
+    pub fn push_inputs(&mut self, values: &[W]) {
+        self.input.extend(values.iter().cloned());
+    }
+
+    pub fn state(&self) -> ProcessorState {
+        return self.state;
+    }
+
+    pub fn memory(&self) -> &PagedMemory<W> {
+        return &self.memory;
+    }
+
+    pub fn memory_mut(&mut self) -> &mut PagedMemory<W> {
+        return &mut self.memory;
+    }
+
+    pub fn is_halted(&self) -> bool {
+        return self.halted;
+    }
+
+    pub fn isa(&self) -> IsaLevel {
+        return self.isa;
+    }
+
+    // Instructions outside the level fault with OutsideIsa. Machines start at the full Day09 set.
+    pub fn set_isa(&mut self, level: IsaLevel) {
+        self.isa = level;
+    }
+
+    pub fn overflow_policy(&self) -> OverflowPolicy {
+        return self.limits.overflow;
+    }
+
+    // Machines trap on overflow unless told otherwise
+    pub fn set_overflow_policy(&mut self, policy: OverflowPolicy) {
+        self.limits.overflow = policy;
+    }
+
+    pub fn limits(&self) -> Limits {
+        return self.limits;
+    }
+
+    // run() fails with LimitExceeded before an instruction that would go over one of the limits,
+    // counting from this call. Output instructions count as outputs, custom opcodes do not.
+    // The overflow policy is replaced by the one in limits.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+        self.instructions = 0;
+        self.outputs = 0;
+        self.elapsed = Duration::from_secs(0);
+    }
+
+    fn check_limits(&self, output: bool, start: Option<Instant>) -> Result<(), IntcodeError> {
+        let limits = &self.limits;
+        let limit = match (limits.max_instructions, limits.max_outputs, limits.max_time, start) {
+            (Some(max), _, _, _) if self.instructions >= max => Some(Limit::Instructions(max)),
+            (_, Some(max), _, _) if output && self.outputs >= max => Some(Limit::Outputs(max)),
+            // Reading the clock is slow compared to an instruction
+            (_, _, Some(max), Some(start)) if self.instructions & 1023 == 0 && self.elapsed + start.elapsed() > max => Some(Limit::Time(max)),
+            _ => None,
+        };
+        return match limit {
+            Some(limit) => Err(IntcodeError::LimitExceeded { word: word_at(&self.memory, self.state.ip), limit, state: self.state }),
+            None => Ok(()),
+        }
+    }
+
+    // Adds an opcode, false if the op code is built in or already registered
+    pub fn register_opcode(&mut self, opcode: CustomOpcode<W>) -> bool {
+        return self.opcodes.register(opcode);
+    }
+
+    // Starts recording executed and written addresses, see CodeTracker
+    pub fn track_code(&mut self) {
+        if self.tracker.is_none() {
+            self.tracker = Some(CodeTracker::new());
+        }
+    }
+
+    pub fn code_tracker(&self) -> Option<&CodeTracker<W>> {
+        return self.tracker.as_ref();
+    }
+
+    // Input pushed but not yet consumed
+    pub fn pending_input(&self) -> Vec<W> {
+        return self.input.iter().cloned().collect();
+    }
+
+    // Runs until the machine produces output, needs input that has not been pushed, or halts
+    pub fn run(&mut self) -> Result<Status<W>, IntcodeError> {
+        let start = self.limits.max_time.map(|_| Instant::now());
+        let result = self.run_from(start);
+        if let Some(start) = start {
+            self.elapsed += start.elapsed();
+        }
+        return result;
+    }
+
+    fn run_from(&mut self, start: Option<Instant>) -> Result<Status<W>, IntcodeError> {
+        loop {
+            if let Some(data) = self.output.pop_front() {
+                return Ok(Status::Output(data));
+            }
+            if self.halted {
+                return Ok(Status::Halted);
+            }
+            if let Some(opcode) = self.opcodes.get(word_at(&self.memory, self.state.ip)) {
+                let ip = self.state.ip;
+                let params = opcode.decode(&ip, &self.memory)?;
+                if !self.isa.allows_custom() {
+                    return Err(IntcodeError::OutsideIsa { ip, word: word_at(&self.memory, ip), level: self.isa });
+                }
+                if self.input.len() < opcode.inputs {
+                    return Ok(Status::NeedsInput);
+                }
+                self.check_limits(false, start)?;
+                if self.limits.is_bounded() {
+                    self.instructions += 1;
+                }
+                let state = self.state;
+                let written: Vec<Word> = params.iter().zip(opcode.params.iter())
+                    .filter(|(_, rule)| **rule == ParameterRule::Write)
+                    .filter_map(|(param, _)| state.address(*param))
+                    .collect();
+                if let Some(tracker) = self.tracker.as_mut() {
+                    tracker.record_instruction(ip, opcode.size());
+                }
+                let mut io = MachineIO { input: &mut self.input, output: &mut self.output };
+                match (opcode.execute)(&params, &mut self.memory, &mut self.state, &mut io)? {
+                    Some(next) => self.state.ip = next,
+                    None => self.halted = true,
+                }
+                if let Some(tracker) = self.tracker.as_mut() {
+                    for address in written {
+                        tracker.record_write(ip, address, self.memory.load(address).unwrap_or_else(|| W::from(0)));
+                    }
+                }
+                continue;
+            }
+            // Decoded once for the level check, the input check, the tracker and execution
+            let ip = self.state.ip;
+            let instruction = W::decode(&ip, &self.memory)?;
+            if !self.isa.allows(&instruction) {
+                return Err(IntcodeError::OutsideIsa { ip, word: word_at(&self.memory, ip), level: self.isa });
+            }
+            if let (Input { .. }, true) = (instruction, self.input.is_empty()) {
+                return Ok(Status::NeedsInput);
+            }
+            let output = matches!(instruction, Output { .. });
+            self.check_limits(output, start)?;
+            if self.limits.is_bounded() {
+                self.instructions += 1;
+                self.outputs += output as u64;
+            }
+            let written = match self.tracker.as_mut() {
+                Some(tracker) => {
+                    tracker.record_instruction(ip, instruction.size());
+                    let state = self.state;
+                    instruction.destination().and_then(|dst| state.address(dst))
+                },
+                None => None,
+            };
+            let mut io = MachineIO { input: &mut self.input, output: &mut self.output };
+            self.halted = execute_instruction(instruction, &mut self.memory, &mut self.state, &mut io, self.limits.overflow)?;
+            if let (Some(tracker), Some(address)) = (self.tracker.as_mut(), written) {
+                tracker.record_write(ip, address, self.memory.load(address).unwrap_or_else(|| W::from(0)));
+            }
+        }
+    }
+
+    // Runs to the end with the given input and returns all output
+    pub fn run_to_end(&mut self) -> Result<Vec<W>, IntcodeError> {
+        let mut output = vec![];
+        loop {
+            match self.run()? {
+                Status::Output(data) => output.push(data),
+                Status::Halted => return Ok(output),
+                Status::NeedsInput => {
+                    let word = word_at(&self.memory, self.state.ip);
+                    return Err(IntcodeError::UnexpectedInput { ip: self.state.ip, word, message: Message::Shutdown });
+                },
+            }
+        }
+    }
 }
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
-enum Instruction {
+pub enum Instruction {
     Add {op1 :Parameter, op2: Parameter, dst: Parameter},
     Multiply {op1 :Parameter, op2: Parameter, dst: Parameter},
     Input {dst :Parameter},
@@ -67,121 +779,265 @@ enum Instruction {
     AdjustRelativeBase {op :Parameter},
 }
 
+impl Instruction {
+    pub fn op_code(&self) -> Word {
+        return match self {
+            Add { .. } => 1,
+            Multiply { .. } => 2,
+            Input { .. } => 3,
+            Output { .. } => 4,
+            JumpIfTrue { .. } => 5,
+            JumpIfFalse { .. } => 6,
+            LessThan { .. } => 7,
+            Equals { .. } => 8,
+            AdjustRelativeBase { .. } => 9,
+            Halt => 99,
+        }
+    }
+
+    pub fn params(&self) -> Vec<Parameter> {
+        return match *self {
+            Add { op1, op2, dst } => vec![op1, op2, dst],
+            Multiply { op1, op2, dst } => vec![op1, op2, dst],
+            Input { dst } => vec![dst],
+            Output { src } => vec![src],
+            JumpIfTrue { cond, target } => vec![cond, target],
+            JumpIfFalse { cond, target } => vec![cond, target],
+            LessThan { op1, op2, dst } => vec![op1, op2, dst],
+            Equals { op1, op2, dst } => vec![op1, op2, dst],
+            AdjustRelativeBase { op } => vec![op],
+            Halt => vec![],
+        }
+    }
+
+    pub fn mnemonic(&self) -> &'static str {
+        return match self {
+            Add { .. } => "add",
+            Multiply { .. } => "mul",
+            Input { .. } => "in",
+            Output { .. } => "out",
+            JumpIfTrue { .. } => "jt",
+            JumpIfFalse { .. } => "jf",
+            LessThan { .. } => "lt",
+            Equals { .. } => "eq",
+            AdjustRelativeBase { .. } => "arb",
+            Halt => "hlt",
+        }
+    }
+
+    // Parameter the instruction writes its result to
+    pub fn destination(&self) -> Option<Parameter> {
+        return match *self {
+            Add { dst, .. } | Multiply { dst, .. } | Input { dst } | LessThan { dst, .. } | Equals { dst, .. } => Some(dst),
+            _ => None,
+        }
+    }
+
+    // Number of words including the op code
+    pub fn size(&self) -> Word {
+        return 1 + self.params().len() as Word;
+    }
+
+    pub fn encode(&self) -> Vec<Word> {
+        let params = self.params();
+        let mut code = self.op_code();
+        let mut factor = 100;
+        for p in params.iter() {
+            code += factor * p.mode();
+            factor *= 10;
+        }
+        let mut words = vec![code];
+        words.extend(params.iter().map(|p| p.value()));
+        return words;
+    }
+}
+
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
-enum Parameter {
+pub enum Parameter {
     Pos(Word),
     Imm(Word),
     Rel(Word),
 }
 
-fn read_params(ip: &Word, memory :&Memory, num: Word) -> Vec<Parameter> {
+impl Parameter {
+    pub fn mode(&self) -> Word {
+        return match self {
+            Pos(_) => 0,
+            Imm(_) => 1,
+            Rel(_) => 2,
+        }
+    }
+
+    pub fn value(&self) -> Word {
+        return match *self {
+            Pos(v) => v,
+            Imm(v) => v,
+            Rel(v) => v,
+        }
+    }
+}
+
+// Same syntax as the assembler
+impl fmt::Display for Parameter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Pos(v) => write!(f, "[{}]", v),
+            Imm(v) => write!(f, "{}", v),
+            Rel(v) if v < 0 => write!(f, "[rb-{}]", -v),
+            Rel(v) => write!(f, "[rb+{}]", v),
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let params: Vec<String> = self.params().iter().map(|p| p.to_string()).collect();
+        if params.is_empty() {
+            write!(f, "{}", self.mnemonic())
+        } else {
+            write!(f, "{:<4} {}", self.mnemonic(), params.join(", "))
+        }
+    }
+}
+
+pub(crate) fn read_params<M: Addressable + ?Sized>(ip: &Word, memory :&M, num: Word) -> Result<Vec<Parameter>, IntcodeError> {
+    let word = memory.read(*ip).unwrap_or(0);
     let mut params: Vec<Parameter> = vec![];
-    let mut param_code = memory[*ip as usize] / 100;
+    let mut param_code = word / 100;
     for i in 1..=num {
-        let val = memory[(ip + i) as usize];
+        let val = match memory.read(ip + i) {
+            Some(val) => val,
+            None => return Err(IntcodeError::AddressOutOfRange { ip: *ip, word, address: ip + i }),
+        };
         let p = match param_code % 10 {
             0 => Parameter::Pos(val),
             1 => Parameter::Imm(val),
             2 => Parameter::Rel(val),
-            _ => panic!("Invalid param: {}", memory[*ip as usize]/100),
+            _ => return Err(IntcodeError::InvalidParameterMode { ip: *ip, word }),
         };
         param_code /= 10;
         params.push(p)
     }
-    return params;
+    return Ok(params);
 }
 
-fn decode_instruction(ip: &Word, memory :&Memory) -> Instruction {
-    let op_code = memory[*ip as usize] % 100;
-    return match op_code {
+pub fn decode_instruction<M: Addressable + ?Sized>(ip: &Word, memory :&M) -> Result<Instruction, IntcodeError> {
+    let word = match memory.read(*ip) {
+        Some(word) => word,
+        None => return Err(IntcodeError::AddressOutOfRange { ip: *ip, word: 0, address: *ip }),
+    };
+    let op_code = word % 100;
+    let instruction = match op_code {
         1 => {
-            let params = read_params(ip, &memory, 3);
+            let params = read_params(ip, memory, 3)?;
             Add { op1: params[0], op2: params[1], dst: params[2] }
         },
         2 => {
-            let params = read_params(ip, &memory, 3);
+            let params = read_params(ip, memory, 3)?;
             Multiply { op1: params[0], op2: params[1], dst: params[2] }
         },
         3 => {
-            let params = read_params(ip, &memory, 1);
+            let params = read_params(ip, memory, 1)?;
             Input { dst: params[0] }
         },
         4 => {
-            let params = read_params(ip, &memory, 1);
+            let params = read_params(ip, memory, 1)?;
             Output { src: params[0] }
         },
         5 => {
-            let params = read_params(ip, &memory, 2);
+            let params = read_params(ip, memory, 2)?;
             JumpIfTrue { cond: params[0], target: params[1] }
         },
         6 => {
-            let params = read_params(ip, &memory, 2);
+            let params = read_params(ip, memory, 2)?;
             JumpIfFalse { cond: params[0], target: params[1] }
         },
         7 => {
-            let params = read_params(ip, &memory, 3);
+            let params = read_params(ip, memory, 3)?;
             LessThan { op1: params[0], op2: params[1], dst: params[2] }
         },
         8 => {
-            let params = read_params(ip, &memory, 3);
+            let params = read_params(ip, memory, 3)?;
             Equals { op1: params[0], op2: params[1], dst: params[2] }
         },
         9 => {
-            let params = read_params(ip, &memory, 1);
+            let params = read_params(ip, memory, 1)?;
             AdjustRelativeBase { op: params[0] }
         },
         99 => Halt,
         _ => {
-            panic!("Unknown instruction: {:?}", memory[*ip as usize]);
+            return Err(IntcodeError::UnknownInstruction { ip: *ip, word });
         },
     };
+    return Ok(instruction);
+}
+
+// Like decode_instruction, but returns None for words that are not valid instructions
+pub(crate) fn try_decode_instruction<M: Addressable + ?Sized>(ip: &Word, memory :&M) -> Option<Instruction> {
+    return decode_instruction(ip, memory).ok();
 }
 
-fn write_raw(memory :&mut Memory, address: &Word, val: Word) {
-    let a = *address as usize;
-    if a >= memory.len() {
-        println!("Out of memory write");
-        panic!("Writing out of memory");
+fn out_of_range<W: WordType>(memory: &PagedMemory<W>, state: &ProcessorState, address: Word) -> IntcodeError {
+    let word = word_at(memory, state.ip);
+    return IntcodeError::AddressOutOfRange { ip: state.ip, word, address };
+}
+
+fn overflow<W: WordType>(memory: &PagedMemory<W>, state: &ProcessorState) -> IntcodeError {
+    let word = word_at(memory, state.ip);
+    return IntcodeError::Overflow { ip: state.ip, word };
+}
+
+fn write_raw<W: WordType>(memory :&mut PagedMemory<W>, state: &ProcessorState, address: &Word, val: W) -> Result<(), IntcodeError> {
+    if !memory.store(*address, val) {
+        return Err(out_of_range(memory, state, *address));
     }
-    memory[a] = val;
+    return Ok(());
 }
 
-fn write(mut memory :&mut Memory, state: &ProcessorState, val: Word, dst: Parameter) {
+fn write<W: WordType>(mut memory :&mut PagedMemory<W>, state: &ProcessorState, val: W, dst: Parameter) -> Result<(), IntcodeError> {
     match dst {
-        Imm(_)   => panic!("Writing IMM"),
+        Imm(_) => {
+            let word = word_at(memory, state.ip);
+            return Err(IntcodeError::WriteToImmediate { ip: state.ip, word });
+        },
         Pos(p) => {
-            write_raw(&mut memory, &p, val);
+            return write_raw(&mut memory, state, &p, val);
         },
         Rel(p) => {
             let address = state.relative_base + p;
-            write_raw(&mut memory, &address, val);
+            return write_raw(&mut memory, state, &address, val);
         }
     }
 }
 
-fn load_raw(memory: &Memory, address :&Word) -> Word {
-    let a = *address as usize;
-    if a >= memory.len() {
-        println!("Out of memory load");
-        panic!("Loading out of memory");
+fn load_raw<W: WordType>(memory: &PagedMemory<W>, state: &ProcessorState, address :&Word) -> Result<W, IntcodeError> {
+    return match memory.load(*address) {
+        Some(val) => Ok(val),
+        None => Err(out_of_range(memory, state, *address)),
     }
-    let val = memory[a];
-    //println!("Loaded: {} <- mem[{}]", val, p);
-    return val;
 }
 
-fn load(memory :&Memory, state: &ProcessorState, src: Parameter) -> Word {
+// For custom opcodes, reads or writes a parameter of the instruction at state.ip
+pub fn load_parameter<W: WordType>(memory: &PagedMemory<W>, state: &ProcessorState, src: Parameter) -> Result<W, IntcodeError> {
+    return load(memory, state, src);
+}
+
+pub fn store_parameter<W: WordType>(memory: &mut PagedMemory<W>, state: &ProcessorState, dst: Parameter, val: W) -> Result<(), IntcodeError> {
+    return write(memory, state, val, dst);
+}
+
+fn load<W: WordType>(memory :&PagedMemory<W>, state: &ProcessorState, src: Parameter) -> Result<W, IntcodeError> {
     match src {
         Imm(v) => {
             //println!("Load const: {}", v);
-            return v;
+            return Ok(W::from(v));
         }
         Pos(p) => {
-            return load_raw(memory, &p);
+            return load_raw(memory, state, &p);
         },
         Rel(offset) => {
             let address = state.relative_base + offset;
-            return load_raw(&memory, &address);
+            return load_raw(&memory, state, &address);
         }
     }
 }
@@ -198,155 +1054,257 @@ fn split_and_parse(s :&str) -> Vec<Word> {
    return split.map(|x| x.parse::<Word>().unwrap()).collect();
 }
 
-pub fn execute_with_result(initial: &Memory, in_data: Vec<Word>) -> OutputData {
-    let (input, pin): (SyncSender<Message>, Receiver<Message>) = mpsc::sync_channel(0);
-    let (pout, output): (SyncSender<Message>, Receiver<Message>) = mpsc::sync_channel(0);
-    let mem = initial.clone();
+pub fn execute_with_result(initial: &Memory, in_data: Vec<Word>) -> Result<OutputData, IntcodeError> {
+    let mut io = BufferIO::new(in_data);
+    execute(initial, &mut io)?;
+    return Ok(io.output);
+}
+
+// Results that do not fit a Word fail with Overflow. They used to wrap around in release
+// builds and panic in debug builds, execute_with_limits with OverflowPolicy::Wrap still wraps.
+pub fn execute(initial: &Memory, io: &mut dyn IO) -> Result<PagedMemory, IntcodeError> {
+    return execute_with_memory_limit(initial, io, DEFAULT_MEMORY_LIMIT);
+}
 
-    let child = thread::spawn(move || {
-        let mut io = StandardIO {
-            stdin: pin,
-            stdout: pout,
+// Addresses at or above limit fail with AddressOutOfRange
+pub fn execute_with_memory_limit(initial: &Memory, io: &mut dyn IO, limit: Word) -> Result<PagedMemory, IntcodeError> {
+
+    let mut mem = PagedMemory::from_image(initial, limit);
+    let mut state = ProcessorState::new();
+
+    loop {
+        if execute_step(&mut mem, &mut state, io, OverflowPolicy::default())? {
+            break;
+        }
+    }
+    return Ok(mem);
+
+}
+
+// Like execute, but fails with LimitExceeded when one of the limits is reached.
+// The time limit is checked between instructions, so it does not interrupt an input that blocks.
+pub fn execute_with_limits(initial: &Memory, io: &mut dyn IO, limits: &Limits) -> Result<PagedMemory, IntcodeError> {
+    let mut mem = load_program(initial);
+    let mut state = ProcessorState::new();
+    let start = Instant::now();
+    let mut instructions: u64 = 0;
+    let mut outputs: u64 = 0;
+
+    loop {
+        let instruction = decode_instruction(&state.ip, &mem)?;
+        let limit = match (limits.max_instructions, limits.max_outputs, limits.max_time, instruction) {
+            (Some(max), _, _, _) if instructions >= max => Some(Limit::Instructions(max)),
+            (_, Some(max), _, Output { .. }) if outputs >= max => Some(Limit::Outputs(max)),
+            // Reading the clock is slow compared to an instruction
+            (_, _, Some(max), _) if instructions & 1023 == 0 && start.elapsed() > max => Some(Limit::Time(max)),
+            _ => None,
         };
-        execute(&mem, &mut io);
-    });
-    let mut inp = VecDeque::from(in_data);
+        if let Some(limit) = limit {
+            let word = mem.load(state.ip).unwrap_or(0);
+            return Err(IntcodeError::LimitExceeded { word, limit, state });
+        }
+        if let Output { .. } = instruction {
+            outputs += 1;
+        }
+        instructions += 1;
+        if execute_instruction(instruction, &mut mem, &mut state, io, limits.overflow)? {
+            break;
+        }
+    }
+    return Ok(mem);
+}
+
+// The output produced before an error is returned with it
+pub fn execute_with_result_limited(initial: &Memory, in_data: Vec<Word>, limits: &Limits) -> Result<OutputData, (IntcodeError, OutputData)> {
+    let mut io = BufferIO::new(in_data);
+    return match execute_with_limits(initial, &mut io, limits) {
+        Ok(_) => Ok(io.output),
+        Err(error) => Err((error, io.output)),
+    }
+}
+
+// Like execute, but decodes every address only once, see InstructionCache.
+// The cache can be shared by many runs of the same program.
+pub fn execute_cached(initial: &Memory, io: &mut dyn IO, cache: &mut InstructionCache, policy: OverflowPolicy) -> Result<PagedMemory, IntcodeError> {
+    let mut mem = load_program(initial);
+    let mut state = ProcessorState::new();
+    cache.prepare(initial);
 
-    let mut result = vec![];
     loop {
-        match output.recv() {
-            Ok(message) => {
-                match message {
-                    Message::Data(data) => result.push(data),
-                    Message::RequestInput => {
-                        let data = inp.pop_front().unwrap();
-                        input.send(Message::Data(data)).unwrap();
-                    },
-                    Message::Shutdown => break,
-                }
-            }
-            Err(error) => {
-                panic!("Error: {:?}", error.to_string());
-            }
+        let instruction = cache.decode(&state.ip, &mem)?;
+        let written = instruction.destination().and_then(|dst| state.address(dst));
+        let halted = execute_instruction(instruction, &mut mem, &mut state, io, policy)?;
+        if let Some(address) = written {
+            cache.invalidate(address);
+        }
+        if halted {
+            break;
         }
     }
-    child.join().unwrap_err();
-    return result;
+    return Ok(mem);
 }
 
-pub fn execute(initial: &Memory, io: &mut dyn IO) -> (Memory) {
+pub fn execute_with_result_cached(initial: &Memory, in_data: Vec<Word>, cache: &mut InstructionCache) -> Result<OutputData, IntcodeError> {
+    let mut io = BufferIO::new(in_data);
+    execute_cached(initial, &mut io, cache, OverflowPolicy::default())?;
+    return Ok(io.output);
+}
+
+// Like execute, but also collects execution statistics
+pub fn execute_profiled(initial: &Memory, io: &mut dyn IO) -> Result<(PagedMemory, Profile), IntcodeError> {
+    let mut mem = load_program(initial);
+    let mut state = ProcessorState::new();
+    let mut profile = Profile::new();
 
-    const LEN :i64 = 32 * 1024; // Should be enough..
-    let mut mem = vec![0; LEN as usize];
-    for i in 0..initial.len() {
-        mem[i] = initial[i];
+    loop {
+        let instruction = decode_instruction(&state.ip, &mem)?;
+        profile.record(&instruction, &state);
+        let halted = execute_step(&mut mem, &mut state, io, OverflowPolicy::default())?;
+        profile.record_state(&state);
+        if halted {
+            break;
+        }
     }
-    let mut state = ProcessorState { ip: 0, relative_base: 0 };
+    return Ok((mem, profile));
+}
+
+// Like execute, but also records every executed instruction
+pub fn execute_traced(initial: &Memory, io: &mut dyn IO) -> Result<(PagedMemory, Trace), IntcodeError> {
+    let mut mem = load_program(initial);
+    let mut state = ProcessorState::new();
+    let mut trace = Trace::new();
 
     loop {
-        if execute_step(&mut mem, &mut state, io) {
+        let (entry, halted) = traced_step(&mut mem, &mut state, io)?;
+        trace.entries.push(entry);
+        if halted {
             break;
         }
     }
-    return mem;
+    return Ok((mem, trace));
+}
+
+pub(crate) fn load_program(initial: &Memory) -> PagedMemory {
+    return PagedMemory::from_image(initial, DEFAULT_MEMORY_LIMIT);
+}
+
+pub(crate) fn execute_step(mem: &mut PagedMemory, state: &mut ProcessorState, io: &mut dyn IO, policy: OverflowPolicy) -> Result<bool, IntcodeError> {
+    let instruction = decode_instruction(&state.ip, mem)?;
+    return execute_instruction(instruction, mem, state, io, policy);
+}
 
+// Like load, but immediates are read from the instruction in memory, the decoded Word may be cut
+fn load_operand<W: WordType>(memory :&PagedMemory<W>, state: &ProcessorState, src: Parameter, index: Word) -> Result<W, IntcodeError> {
+    return match src {
+        Imm(v) => Ok(W::immediate(memory, state.ip + index, v)),
+        _ => load(memory, state, src),
+    }
 }
-fn execute_step(mut mem: &mut Memory, mut state: &mut ProcessorState, io: &mut dyn IO) -> bool{
 
-    let instruction = decode_instruction(&state.ip, &mem);
+// Executes an instruction already decoded from state.ip
+pub(crate) fn execute_instruction<W: WordType>(instruction: Instruction, mut mem: &mut PagedMemory<W>, mut state: &mut ProcessorState, io: &mut dyn IO<W>, policy: OverflowPolicy) -> Result<bool, IntcodeError> {
 //    println!("Executing: {:?} {:?}", state, instruction);
  //   stdout().flush();
 //    sleep(Duration::from_millis(100));
     match instruction {
         Add {op1, op2, dst} => {
-            let v1 = load(&mem, &state, op1);
-            let v2 = load(&mem, &state, op2);
-            let res = v1 + v2;
-            write(&mut mem, &state, res, dst);
+            let v1 = load_operand(&mem, &state, op1, 1)?;
+            let v2 = load_operand(&mem, &state, op2, 2)?;
+            let res = policy.add(&v1, &v2).ok_or_else(|| overflow(mem, state))?;
+            write(&mut mem, &state, res, dst)?;
             state.ip += 4;
         },
         Multiply {op1, op2, dst} => {
-            let v1 = load(&mem, &state, op1);
-            let v2 = load(&mem, &state, op2);
-            let res = v1 * v2;
-            write(&mut mem, &state, res, dst);
+            let v1 = load_operand(&mem, &state, op1, 1)?;
+            let v2 = load_operand(&mem, &state, op2, 2)?;
+            let res = policy.mul(&v1, &v2).ok_or_else(|| overflow(mem, state))?;
+            write(&mut mem, &state, res, dst)?;
             state.ip += 4;
         },
         Input {dst} => {
             io.send(RequestInput);
             let val = match io.receive() {
                 Message::Data(data) => data,
-                x => panic!(x),
+                message => {
+                    let word = word_at(mem, state.ip);
+                    return Err(IntcodeError::UnexpectedInput { ip: state.ip, word, message: message.to_word() });
+                },
             };
-            write(&mut mem, &state, val, dst);
+            write(&mut mem, &state, val, dst)?;
             state.ip += 2;
         },
         Output {src} => {
-            let val = load(&mem, &state, src);
+            let val = load_operand(&mem, &state, src, 1)?;
 //            println!("Output: {}", val);
             io.send(Message::Data(val));
             state.ip += 2;
         }
         JumpIfTrue { cond, target } => {
-            let val = load(&mem, &state, cond);
-            if val != 0 {
-                let target = load(&mem, &state, target);
-                state.ip = target;
+            let val = load_operand(&mem, &state, cond, 1)?;
+            if val != W::from(0) {
+                let target = load_operand(&mem, &state, target, 2)?;
+                state.ip = target.to_word().ok_or_else(|| overflow(mem, state))?;
             } else {
                 state.ip += 3
             }
         }
         JumpIfFalse { cond, target } => {
-            let val = load(&mem, &state, cond);
-            if val == 0 {
-                let target = load(&mem, &state, target);
-                state.ip = target;
+            let val = load_operand(&mem, &state, cond, 1)?;
+            if val == W::from(0) {
+                let target = load_operand(&mem, &state, target, 2)?;
+                state.ip = target.to_word().ok_or_else(|| overflow(mem, state))?;
             } else {
                 state.ip += 3
             }
         }
         LessThan { op1, op2, dst } => {
-            let val1 = load(&mem, &state, op1);
-            let val2 = load(&mem, &state, op2);
+            let val1 = load_operand(&mem, &state, op1, 1)?;
+            let val2 = load_operand(&mem, &state, op2, 2)?;
             let result;
             if val1 < val2 {
-                result = 1;
+                result = W::from(1);
             } else {
-                result = 0;
+                result = W::from(0);
             }
-            write(&mut mem, &state, result, dst);
+            write(&mut mem, &state, result, dst)?;
             state.ip += 4
         }
         Equals { op1, op2, dst } => {
-            let val1 = load(&mem, &state, op1);
-            let val2 = load(&mem, &state, op2);
+            let val1 = load_operand(&mem, &state, op1, 1)?;
+            let val2 = load_operand(&mem, &state, op2, 2)?;
             let result;
 //            print!("Comparing: {} and {}", val1, val2);
             if val1 == val2 {
-                result = 1;
+                result = W::from(1);
             } else {
-                result = 0;
+                result = W::from(0);
             }
-            write(&mut mem, &state, result, dst);
+            write(&mut mem, &state, result, dst)?;
             state.ip += 4
         }
         Halt => {
             io.send(Message::Shutdown);
 //            println!("Halt!");
-            return true;
+            return Ok(true);
         },
         AdjustRelativeBase { op } => {
             //println!("Adjusting!");
-            state.relative_base += load(&mut mem, &state, op);
+            let offset = load_operand(&mem, &state, op, 1)?.to_word().ok_or_else(|| overflow(mem, state))?;
+            state.relative_base = state.relative_base.checked_add(offset).ok_or_else(|| overflow(mem, state))?;
             state.ip += 2;
         }
     }
-    return false;
+    return Ok(false);
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::intmachine::{split_and_parse, decode_instruction, execute_with_result};
+    use crate::intmachine::{split_and_parse, decode_instruction, execute_with_result, execute_with_memory_limit, execute_with_result_limited, execute_with_limits, execute_cached, Limit, Limits, ProcessorState, IntcodeError, Message, BufferIO, PagedMemory, PAGE_SIZE, IntMachine, Status, IsaLevel, Word};
+    use crate::icache::InstructionCache;
+    use crate::wide::{OverflowPolicy, Promoting};
+    use num::BigInt;
+    use std::env;
+    use std::time::Duration;
     use crate::intmachine::Instruction::{Add, Multiply};
     use crate::intmachine::Parameter::{Pos, Imm};
 
@@ -358,43 +1316,43 @@ mod tests {
     #[test]
     fn test_decode() {
         assert_eq!(decode_instruction(&0, &vec![1,0,0,0,99]),
-                   Add {op1: Pos(0), op2: Pos(0), dst: Pos(0) });
+                   Ok(Add {op1: Pos(0), op2: Pos(0), dst: Pos(0) }));
         assert_eq!(decode_instruction(&0, &vec![1002,4,3,4,33]),
-                   Multiply {op1: Pos(4), op2: Imm(3), dst: Pos(4) });
+                   Ok(Multiply {op1: Pos(4), op2: Imm(3), dst: Pos(4) }));
     }
 
     #[test]
     fn testIO() {
-        assert_eq!(execute_with_result(&vec![3,0,4,0,99], vec![73]), vec![73]);
+        assert_eq!(execute_with_result(&vec![3,0,4,0,99], vec![73]), Ok(vec![73]));
     }
 
     #[test]
     fn test_comparision() {
         // Using position mode, consider whether the input is equal to 8; output 1 (if it is) or 0 (if it is not).
-        assert_eq!(execute_with_result(&vec![3,9,8,9,10,9,4,9,99,-1,8], vec![8]), vec![1]);
-        assert_eq!(execute_with_result(&vec![3,9,8,9,10,9,4,9,99,-1,8], vec![7]), vec![0]);
-        assert_eq!(execute_with_result(&vec![3,9,8,9,10,9,4,9,99,-1,8], vec![9]), vec![0]);
+        assert_eq!(execute_with_result(&vec![3,9,8,9,10,9,4,9,99,-1,8], vec![8]), Ok(vec![1]));
+        assert_eq!(execute_with_result(&vec![3,9,8,9,10,9,4,9,99,-1,8], vec![7]), Ok(vec![0]));
+        assert_eq!(execute_with_result(&vec![3,9,8,9,10,9,4,9,99,-1,8], vec![9]), Ok(vec![0]));
         // Using position mode, consider whether the input is less than 8; output 1 (if it is) or 0 (if it is not).
-        assert_eq!(execute_with_result(&vec![3,9,7,9,10,9,4,9,99,-1,8], vec![8]), vec![0]);
-        assert_eq!(execute_with_result(&vec![3,9,7,9,10,9,4,9,99,-1,8], vec![7]), vec![1]);
-        assert_eq!(execute_with_result(&vec![3,9,7,9,10,9,4,9,99,-1,8], vec![9]), vec![0]);
+        assert_eq!(execute_with_result(&vec![3,9,7,9,10,9,4,9,99,-1,8], vec![8]), Ok(vec![0]));
+        assert_eq!(execute_with_result(&vec![3,9,7,9,10,9,4,9,99,-1,8], vec![7]), Ok(vec![1]));
+        assert_eq!(execute_with_result(&vec![3,9,7,9,10,9,4,9,99,-1,8], vec![9]), Ok(vec![0]));
         // Using immediate mode, consider whether the input is equal to 8; output 1 (if it is) or 0 (if it is not).
-        assert_eq!(execute_with_result(&vec![3,3,1108,-1,8,3,4,3,99], vec![7]), vec![0]);
-        assert_eq!(execute_with_result(&vec![3,3,1108,-1,8,3,4,3,99], vec![8]), vec![1]);
-        assert_eq!(execute_with_result(&vec![3,3,1108,-1,8,3,4,3,99], vec![9]), vec![0]);
+        assert_eq!(execute_with_result(&vec![3,3,1108,-1,8,3,4,3,99], vec![7]), Ok(vec![0]));
+        assert_eq!(execute_with_result(&vec![3,3,1108,-1,8,3,4,3,99], vec![8]), Ok(vec![1]));
+        assert_eq!(execute_with_result(&vec![3,3,1108,-1,8,3,4,3,99], vec![9]), Ok(vec![0]));
         // Using immediate mode, consider whether the input is less than 8; output 1 (if it is) or 0 (if it is not).
-        assert_eq!(execute_with_result(&vec![3,3,1107,-1,8,3,4,3,99], vec![7]), vec![1]);
-        assert_eq!(execute_with_result(&vec![3,3,1107,-1,8,3,4,3,99], vec![8]), vec![0]);
-        assert_eq!(execute_with_result(&vec![3,3,1107,-1,8,3,4,3,99], vec![9]), vec![0]);
+        assert_eq!(execute_with_result(&vec![3,3,1107,-1,8,3,4,3,99], vec![7]), Ok(vec![1]));
+        assert_eq!(execute_with_result(&vec![3,3,1107,-1,8,3,4,3,99], vec![8]), Ok(vec![0]));
+        assert_eq!(execute_with_result(&vec![3,3,1107,-1,8,3,4,3,99], vec![9]), Ok(vec![0]));
     }
 
     #[test]
     fn test_jumps() {
         // Here are some jump tests that take an input, then output 0 if the input was zero or 1 if the input was non-zero:
-        assert_eq!(execute_with_result(&vec![3,12,6,12,15,1,13,14,13,4,13,99,-1,0,1,9], vec![9]), vec![1]);
-        assert_eq!(execute_with_result(&vec![3,12,6,12,15,1,13,14,13,4,13,99,-1,0,1,9], vec![0]), vec![0]);
-        assert_eq!(execute_with_result(&vec![3,3,1105,-1,9,1101,0,0,12,4,12,99,1], vec![9]), vec![1]);
-        assert_eq!(execute_with_result(&vec![3,3,1105,-1,9,1101,0,0,12,4,12,99,1], vec![0]), vec![0]);
+        assert_eq!(execute_with_result(&vec![3,12,6,12,15,1,13,14,13,4,13,99,-1,0,1,9], vec![9]), Ok(vec![1]));
+        assert_eq!(execute_with_result(&vec![3,12,6,12,15,1,13,14,13,4,13,99,-1,0,1,9], vec![0]), Ok(vec![0]));
+        assert_eq!(execute_with_result(&vec![3,3,1105,-1,9,1101,0,0,12,4,12,99,1], vec![9]), Ok(vec![1]));
+        assert_eq!(execute_with_result(&vec![3,3,1105,-1,9,1101,0,0,12,4,12,99,1], vec![0]), Ok(vec![0]));
     }
 
 
@@ -402,28 +1360,274 @@ mod tests {
     fn test_complex() {
         let program = vec![3, 21, 1008, 21, 8, 20, 1005, 20, 22, 107, 8, 21, 20, 1006, 20, 31, 1106, 0, 36, 98, 0, 0, 1002, 21, 125, 20, 4, 20, 1105, 1, 46, 104, 999, 1105, 1, 46, 1101, 1000, 1, 20, 4, 20, 1105, 1, 46, 98, 99];
 
-        assert_eq!(execute_with_result(&program, vec![8]), vec![1000]);
-        assert_eq!(execute_with_result(&program, vec![9]), vec![1001]);
-        assert_eq!(execute_with_result(&program, vec![7]), vec![999]);
+        assert_eq!(execute_with_result(&program, vec![8]), Ok(vec![1000]));
+        assert_eq!(execute_with_result(&program, vec![9]), Ok(vec![1001]));
+        assert_eq!(execute_with_result(&program, vec![7]), Ok(vec![999]));
     }
 
     #[test]
     fn test_indirect() {
         let program = vec![109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99];
         let expected = program.clone();
-        assert_eq!(execute_with_result(&program, vec![]), expected);
+        assert_eq!(execute_with_result(&program, vec![]), Ok(expected));
     }
 
     #[test]
     fn test_large_number() {
         let program = vec![1102,34915192,34915192,7,4,7,99,0];
-        let result = execute_with_result(&program, vec![]);
+        let result = execute_with_result(&program, vec![]).unwrap();
         let s = result[0].to_string();
         assert_eq!(s.len(), 16);
+
+        // The square of the result does not fit in a Word
+        let program = vec![1102,34915192349151923,34915192349151923,7,4,7,99,0];
+        assert_eq!(execute_with_result(&program, vec![]), Err(IntcodeError::Overflow { ip: 0, word: 1102 }));
+        let mut machine = IntMachine::new(&vec![1,5,5,0,99,std::i64::MAX]);
+        assert_eq!(machine.run(), Err(IntcodeError::Overflow { ip: 0, word: 1 }));
+    }
+
+    #[test]
+    fn test_large_number_policies() {
+        // The same square with one setting: trap, wrap around or grow the word
+        let program = vec![1102,34915192349151923,34915192349151923,7,4,7,99,0];
+        let square = BigInt::from(34915192349151923i64) * BigInt::from(34915192349151923i64);
+        let run = |policy| {
+            let mut machine: IntMachine<Promoting> = IntMachine::from_program(&program);
+            machine.set_overflow_policy(policy);
+            return machine.run_to_end();
+        };
+        assert_eq!(run(OverflowPolicy::Trap), Err(IntcodeError::Overflow { ip: 0, word: 1102 }));
+        assert_eq!(run(OverflowPolicy::Wrap), Ok(vec![Promoting::Small(34915192349151923i64.wrapping_mul(34915192349151923))]));
+        assert_eq!(run(OverflowPolicy::Promote), Ok(vec![Promoting::Big(square)]));
+
+        // Plain words can not grow
+        let mut machine = IntMachine::new(&program);
+        machine.set_overflow_policy(OverflowPolicy::Promote);
+        assert_eq!(machine.run(), Err(IntcodeError::Overflow { ip: 0, word: 1102 }));
     }
+    #[test]
+    fn test_overflow_policy() {
+        // mul 2^62, 2, [4] then out [4]
+        let program = vec![1102,4611686018427387904,2,7,4,7,99,0];
+        assert_eq!(execute_with_result(&program, vec![]), Err(IntcodeError::Overflow { ip: 0, word: 1102 }));
+        let limits = Limits { overflow: OverflowPolicy::Wrap, ..Limits::default() };
+        assert_eq!(execute_with_result_limited(&program, vec![], &limits), Ok(vec![Word::MIN]));
+        assert_eq!(Limits::default().overflow, OverflowPolicy::Trap);
+
+        let mut io = BufferIO::new(vec![]);
+        let mut cache = InstructionCache::new(program.len());
+        assert_eq!(execute_cached(&program, &mut io, &mut cache, OverflowPolicy::Trap), Err(IntcodeError::Overflow { ip: 0, word: 1102 }));
+        execute_cached(&program, &mut io, &mut cache, OverflowPolicy::Wrap).unwrap();
+        assert_eq!(io.output, vec![Word::MIN]);
+    }
+
     #[test]
     fn test_large_number2() {
         let program2 = vec![104,1125899906842624,99];
-        assert_eq!(execute_with_result(&program2, vec![]), vec![1125899906842624]);
+        assert_eq!(execute_with_result(&program2, vec![]), Ok(vec![1125899906842624]));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(execute_with_result(&vec![1101,1,1,3,42], vec![]),
+                   Err(IntcodeError::UnknownInstruction { ip: 4, word: 42 }));
+        assert_eq!(execute_with_result(&vec![301,0,0,0,99], vec![]),
+                   Err(IntcodeError::InvalidParameterMode { ip: 0, word: 301 }));
+        assert_eq!(execute_with_result(&vec![11101,1,1,3,99], vec![]),
+                   Err(IntcodeError::WriteToImmediate { ip: 0, word: 11101 }));
+        assert_eq!(execute_with_result(&vec![4,-1,99], vec![]),
+                   Err(IntcodeError::AddressOutOfRange { ip: 0, word: 4, address: -1 }));
+        assert_eq!(execute_with_result(&vec![109,-5,204,2,99], vec![]),
+                   Err(IntcodeError::AddressOutOfRange { ip: 2, word: 204, address: -3 }));
+        assert_eq!(execute_with_result(&vec![1105,1,100000], vec![]),
+                   Err(IntcodeError::UnknownInstruction { ip: 100000, word: 0 }));
+        assert_eq!(execute_with_result(&vec![3,0,99], vec![]),
+                   Err(IntcodeError::UnexpectedInput { ip: 0, word: 3, message: Message::Shutdown }));
+    }
+
+    #[test]
+    fn test_paged_memory() {
+        let mut memory = PagedMemory::from_image(&vec![1, 2, 3], 1 << 40);
+        assert_eq!(memory.allocated_pages(), 1);
+        assert_eq!(memory.load(2), Some(3));
+        assert_eq!(memory.load(1 << 39), Some(0));
+        assert_eq!(memory.allocated_pages(), 1);
+        assert!(memory.store(1 << 39, 7));
+        assert_eq!(memory.load(1 << 39), Some(7));
+        assert_eq!(memory.allocated_pages(), 2);
+        assert!(!memory.store(1 << 40, 7));
+        assert_eq!(memory.load(-1), None);
+        assert_eq!(PagedMemory::from_image(&vec![5; 10], 1 << 20).to_vec().len(), PAGE_SIZE as usize);
+        assert_eq!(PagedMemory::from_image(&vec![5; 10], 100).to_vec().len(), 100);
+    }
+
+    #[test]
+    fn test_large_address() {
+        // Copy input to a far away address and output it from there
+        let program = vec![3,3000000000,4,3000000000,99];
+        assert_eq!(execute_with_result(&program, vec![42]), Ok(vec![42]));
+
+        let mut io = BufferIO::new(vec![42]);
+        assert_eq!(execute_with_memory_limit(&program, &mut io, 1000),
+                   Err(IntcodeError::AddressOutOfRange { ip: 0, word: 3, address: 3000000000 }));
+    }
+
+    #[test]
+    fn test_machine() {
+        // Add two inputs, twice
+        let program = vec![3,20,3,21,1,20,21,22,4,22,1105,1,0];
+        let mut machine = IntMachine::new(&program);
+        assert_eq!(machine.run(), Ok(Status::NeedsInput));
+        machine.push_input(1);
+        assert_eq!(machine.run(), Ok(Status::NeedsInput));
+        assert_eq!(machine.state().ip, 2);
+        machine.push_input(2);
+        assert_eq!(machine.run(), Ok(Status::Output(3)));
+        machine.push_inputs(&[10, 20]);
+        assert_eq!(machine.run(), Ok(Status::Output(30)));
+        assert_eq!(machine.run(), Ok(Status::NeedsInput));
+
+        let mut machine = IntMachine::new(&vec![104,1,104,2,99]);
+        assert_eq!(machine.run(), Ok(Status::Output(1)));
+        assert_eq!(machine.run(), Ok(Status::Output(2)));
+        assert_eq!(machine.run(), Ok(Status::Halted));
+        assert!(machine.is_halted());
+        assert_eq!(machine.run(), Ok(Status::Halted));
+    }
+
+    #[test]
+    fn test_run_to_end() {
+        let mut machine = IntMachine::new(&vec![3,9,8,9,10,9,4,9,99,-1,8]);
+        machine.push_input(8);
+        assert_eq!(machine.run_to_end(), Ok(vec![1]));
+        let mut machine = IntMachine::new(&vec![3,0,99]);
+        assert_eq!(machine.run_to_end(),
+                   Err(IntcodeError::UnexpectedInput { ip: 0, word: 3, message: Message::Shutdown }));
+    }
+
+    #[test]
+    fn test_snapshot() {
+        // Adds each input to a running total at 10000 and outputs it
+        let program = vec![3,20,1,20,10000,10000,4,10000,1105,1,0];
+        let mut machine = IntMachine::new(&program);
+        machine.push_inputs(&[5, 6]);
+        assert_eq!(machine.run(), Ok(Status::Output(5)));
+
+        let mut fork = machine.clone();
+        assert_eq!(fork.pending_input(), vec![6]);
+        assert_eq!(fork.run(), Ok(Status::Output(11)));
+        assert_eq!(fork.run(), Ok(Status::NeedsInput));
+        fork.push_input(100);
+        assert_eq!(fork.run(), Ok(Status::Output(111)));
+
+        assert_eq!(machine.run(), Ok(Status::Output(11)));
+        machine.push_input(1);
+        assert_eq!(machine.run(), Ok(Status::Output(12)));
+
+        let restored = IntMachine::from_bytes(&fork.to_bytes()).unwrap();
+        assert_eq!(restored, fork);
+        assert_eq!(restored.memory().load(10000), Some(111));
+        assert_eq!(IntMachine::from_bytes(&fork.to_bytes()[..20]), None);
+
+        let filename = env::temp_dir().join("intmachine_snapshot_test.bin");
+        let filename = filename.to_str().unwrap();
+        machine.save(filename).unwrap();
+        let mut loaded = IntMachine::load(filename).unwrap();
+        assert_eq!(loaded, machine);
+        loaded.push_input(2);
+        assert_eq!(loaded.run(), Ok(Status::Output(14)));
+    }
+
+    #[test]
+    fn test_isa() {
+        let add = decode_instruction(&0, &vec![1,0,0,0]).unwrap();
+        let mul = decode_instruction(&0, &vec![1002,4,3,4]).unwrap();
+        let out = decode_instruction(&0, &vec![104,1]).unwrap();
+        let rel = decode_instruction(&0, &vec![204,1]).unwrap();
+        let arb = decode_instruction(&0, &vec![109,1]).unwrap();
+        assert_eq!([&add, &mul, &out, &rel, &arb].iter().map(|i| IsaLevel::Day02.allows(i)).collect::<Vec<_>>(),
+                   vec![true, false, false, false, false]);
+        assert_eq!([&add, &mul, &out, &rel, &arb].iter().map(|i| IsaLevel::Day05.allows(i)).collect::<Vec<_>>(),
+                   vec![true, true, true, false, false]);
+        assert!([&add, &mul, &out, &rel, &arb].iter().all(|i| IsaLevel::Day09.allows(i)));
+
+        let mut machine = IntMachine::new(&vec![1,0,0,0,99]);
+        machine.set_isa(IsaLevel::Day02);
+        assert_eq!(machine.run(), Ok(Status::Halted));
+        assert_eq!(machine.memory().load(0), Some(2));
+
+        let mut machine = IntMachine::new(&vec![1,0,0,0,1101,1,1,0,99]);
+        machine.set_isa(IsaLevel::Day02);
+        assert_eq!(machine.run(), Err(IntcodeError::OutsideIsa { ip: 4, word: 1101, level: IsaLevel::Day02 }));
+        assert_eq!(machine.state().ip, 4);
+
+        let mut machine = IntMachine::new(&vec![104,1,109,1,99]);
+        machine.set_isa(IsaLevel::Day05);
+        assert_eq!(machine.run(), Ok(Status::Output(1)));
+        assert_eq!(machine.run(), Err(IntcodeError::OutsideIsa { ip: 2, word: 109, level: IsaLevel::Day05 }));
+        machine.set_isa(IsaLevel::Day09);
+        assert_eq!(machine.run(), Ok(Status::Halted));
+    }
+
+    #[test]
+    fn test_limits() {
+        // Counts up forever: out [7], add [7], 1, [7], jt 1, 0
+        let program = vec![4,7,1001,7,1,7,1105,1,0];
+        let limits = Limits { max_instructions: Some(10), ..Limits::default() };
+        let (error, output) = execute_with_result_limited(&program, vec![], &limits).unwrap_err();
+        assert_eq!(error, IntcodeError::LimitExceeded { word: 1001, limit: Limit::Instructions(10), state: ProcessorState { ip: 2, relative_base: 0 } });
+        assert_eq!(error.ip(), 2);
+        assert_eq!(output, vec![1, 2, 3, 4]);
+
+        let limits = Limits { max_outputs: Some(3), ..Limits::default() };
+        let (error, output) = execute_with_result_limited(&program, vec![], &limits).unwrap_err();
+        assert_eq!(error, IntcodeError::LimitExceeded { word: 4, limit: Limit::Outputs(3), state: ProcessorState { ip: 0, relative_base: 0 } });
+        assert_eq!(output, vec![1, 2, 3]);
+
+        let limits = Limits { max_time: Some(Duration::from_millis(10)), ..Limits::default() };
+        let error = execute_with_limits(&program, &mut BufferIO::new(vec![]), &limits).unwrap_err();
+        match error {
+            IntcodeError::LimitExceeded { limit: Limit::Time(_), .. } => {},
+            _ => panic!("Unexpected error: {}", error),
+        }
+
+        // Runs that stay within the limits are not affected
+        let limits = Limits { max_instructions: Some(5), max_outputs: Some(1), max_time: Some(Duration::from_secs(10)), ..Limits::default() };
+        assert_eq!(execute_with_result_limited(&vec![3,9,8,9,10,9,4,9,99,-1,8], vec![8], &limits), Ok(vec![1]));
+    }
+
+    #[test]
+    fn test_machine_limits() {
+        let program = vec![4,7,1001,7,1,7,1105,1,0];
+        let mut machine = IntMachine::new(&program);
+        machine.set_limits(Limits { max_instructions: Some(10), ..Limits::default() });
+        let mut output = vec![];
+        let error = loop {
+            match machine.run() {
+                Ok(Status::Output(data)) => output.push(data),
+                Ok(status) => panic!("Unexpected status: {:?}", status),
+                Err(error) => break error,
+            }
+        };
+        assert_eq!(error, IntcodeError::LimitExceeded { word: 1001, limit: Limit::Instructions(10), state: ProcessorState { ip: 2, relative_base: 0 } });
+        assert_eq!(output, vec![1, 2, 3, 4]);
+
+        // Counting starts again, the stopped instruction has not been executed
+        machine.set_limits(Limits { max_outputs: Some(2), ..Limits::default() });
+        assert_eq!(machine.run(), Ok(Status::Output(5)));
+        assert_eq!(machine.run(), Ok(Status::Output(6)));
+        assert_eq!(machine.run(), Err(IntcodeError::LimitExceeded { word: 4, limit: Limit::Outputs(2), state: ProcessorState { ip: 0, relative_base: 0 } }));
+
+        machine.set_limits(Limits { max_time: Some(Duration::from_millis(10)), ..Limits::default() });
+        let error = loop {
+            if let Err(error) = machine.run() {
+                break error;
+            }
+        };
+        match error {
+            IntcodeError::LimitExceeded { limit: Limit::Time(_), .. } => {},
+            _ => panic!("Unexpected error: {}", error),
+        }
+        assert_eq!(machine.overflow_policy(), OverflowPolicy::Trap);
+    }
+}
@@ -1,3 +1,17 @@
 pub mod mutable_union_set;
 pub mod mutable_graph;
-pub mod intmachine;
\ No newline at end of file
+pub mod intmachine;
+pub mod assembler;
+pub mod disassembler;
+pub mod debugger;
+pub mod profiler;
+pub mod trace;
+pub mod network;
+pub mod opcodes;
+pub mod wide;
+pub mod cfg;
+pub mod selfmod;
+pub mod icache;
+pub mod asyncio;
+pub mod ascii;
+pub mod springscript;
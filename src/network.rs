@@ -0,0 +1,239 @@
+// Deterministic network of Intcode machines, as in day 23.
+//
+// All machines run round-robin in the calling thread. A machine that reads input gets the
+// next packet addressed to it, or -1 when there is none. Every three words it outputs form
+// a packet (dest, x, y). Packets for addresses without a machine go to the NAT policy.
+
+use crate::intmachine::{IntMachine, IntcodeError, Memory, Status, Word};
+use std::collections::VecDeque;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Packet {
+    pub source: Word,
+    pub dest: Word,
+    pub x: Word,
+    pub y: Word,
+}
+
+pub trait NatPolicy {
+    // A packet sent to an address outside the network, false stops the network
+    fn receive(&mut self, packet: Packet) -> bool;
+    // Called when the network is idle, the packet is delivered to wake it up. None stops the network.
+    fn idle(&mut self) -> Option<Packet>;
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum NetworkResult {
+    // The NAT policy refused a packet
+    Stopped,
+    // The network went idle and the NAT policy had nothing to send
+    Idle,
+    // Every machine halted
+    Halted,
+}
+
+pub struct Network {
+    machines: Vec<IntMachine>,
+    queues: Vec<VecDeque<(Word, Word)>>,
+    outputs: Vec<Vec<Word>>,
+    // Set when a machine read -1 and asked for input again without sending anything
+    idle: Vec<bool>,
+}
+
+impl Network {
+    // Boots size copies of program, each machine reads its address first
+    pub fn new(program: &Memory, size: usize) -> Network {
+        let mut machines = vec![];
+        for address in 0..size {
+            let mut machine = IntMachine::new(program);
+            machine.push_input(address as Word);
+            machines.push(machine);
+        }
+        return Network {
+            machines,
+            queues: vec![VecDeque::new(); size],
+            outputs: vec![vec![]; size],
+            idle: vec![false; size],
+        };
+    }
+
+    pub fn size(&self) -> usize {
+        return self.machines.len();
+    }
+
+    pub fn machine(&self, address: Word) -> &IntMachine {
+        return &self.machines[address as usize];
+    }
+
+    // Queues a packet for a machine, false if there is no machine with that address
+    pub fn send(&mut self, packet: Packet) -> bool {
+        return match self.queues.get_mut(packet.dest as usize) {
+            Some(queue) if packet.dest >= 0 => {
+                queue.push_back((packet.x, packet.y));
+                true
+            },
+            _ => false,
+        }
+    }
+
+    // True when every machine is waiting on an empty queue
+    pub fn is_idle(&self) -> bool {
+        return self.machines.iter().enumerate().all(|(i, m)| {
+            m.is_halted() || (self.idle[i] && self.queues[i].is_empty() && self.outputs[i].is_empty())
+        });
+    }
+
+    // Runs until the NAT policy stops the network, or it is idle and the policy has nothing to send
+    pub fn run(&mut self, nat: &mut dyn NatPolicy) -> Result<NetworkResult, IntcodeError> {
+        loop {
+            if let Some(result) = self.round(nat)? {
+                return Ok(result);
+            }
+            if self.machines.iter().all(|m| m.is_halted()) {
+                return Ok(NetworkResult::Halted);
+            }
+            if self.is_idle() {
+                match nat.idle() {
+                    Some(packet) => {
+                        if !self.send(packet) && !nat.receive(packet) {
+                            return Ok(NetworkResult::Stopped);
+                        }
+                    },
+                    None => return Ok(NetworkResult::Idle),
+                }
+            }
+        }
+    }
+
+    // Gives each machine one turn: it gets its next packet, or -1, and runs until it wants more input
+    fn round(&mut self, nat: &mut dyn NatPolicy) -> Result<Option<NetworkResult>, IntcodeError> {
+        for i in 0..self.machines.len() {
+            // Only does something the first time, when the machine reads its address
+            if self.run_machine(i, nat)?.is_none() {
+                return Ok(Some(NetworkResult::Stopped));
+            }
+            if self.machines[i].is_halted() {
+                continue;
+            }
+            let polled = match self.queues[i].pop_front() {
+                Some((x, y)) => {
+                    self.machines[i].push_inputs(&[x, y]);
+                    false
+                },
+                None => {
+                    self.machines[i].push_input(-1);
+                    true
+                },
+            };
+            match self.run_machine(i, nat)? {
+                Some(sent) => self.idle[i] = polled && sent == 0,
+                None => return Ok(Some(NetworkResult::Stopped)),
+            }
+        }
+        return Ok(None);
+    }
+
+    // Runs a machine until it needs input or halts, routing what it sends.
+    // Returns the number of packets sent, None if the NAT policy stopped the network.
+    fn run_machine(&mut self, i: usize, nat: &mut dyn NatPolicy) -> Result<Option<usize>, IntcodeError> {
+        let mut sent = 0;
+        loop {
+            match self.machines[i].run()? {
+                Status::Output(data) => {
+                    self.outputs[i].push(data);
+                    if self.outputs[i].len() == 3 {
+                        let words = self.outputs[i].split_off(0);
+                        let packet = Packet { source: i as Word, dest: words[0], x: words[1], y: words[2] };
+                        sent += 1;
+                        if !self.send(packet) && !nat.receive(packet) {
+                            return Ok(None);
+                        }
+                    }
+                },
+                Status::NeedsInput | Status::Halted => return Ok(Some(sent)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::network::{Network, NatPolicy, NetworkResult, Packet};
+    use crate::assembler::assemble;
+
+    // Forwards each packet to the next address with y + 1, machine 0 starts with (7, 0)
+    fn relay() -> Vec<i64> {
+        return assemble("
+                in   [addr]
+                jf   [addr], start
+        loop:   in   [x]
+                eq   [x], -1, [t]
+                jt   [t], loop
+                in   [y]
+                add  [addr], 1, [dst]
+                add  [y], 1, [y]
+                out  [dst]
+                out  [x]
+                out  [y]
+                jt   1, loop
+        start:  out  1
+                out  7
+                out  0
+                jt   1, loop
+        addr:   data 0
+        x:      data 0
+        y:      data 0
+        t:      data 0
+        dst:    data 0
+        ").unwrap();
+    }
+
+    struct Recorder {
+        received: Vec<Packet>,
+        wakeups: usize,
+    }
+
+    impl NatPolicy for Recorder {
+        fn receive(&mut self, packet: Packet) -> bool {
+            self.received.push(packet);
+            return true;
+        }
+
+        fn idle(&mut self) -> Option<Packet> {
+            if self.wakeups == 1 {
+                return None;
+            }
+            self.wakeups += 1;
+            let last = self.received.last()?;
+            return Some(Packet { source: 255, dest: 0, x: last.x, y: last.y });
+        }
+    }
+
+    #[test]
+    fn test_network() {
+        let mut network = Network::new(&relay(), 3);
+        let mut nat = Recorder { received: vec![], wakeups: 0 };
+        assert_eq!(network.run(&mut nat), Ok(NetworkResult::Idle));
+        assert_eq!(nat.received, vec![
+            Packet { source: 2, dest: 3, x: 7, y: 2 },
+            Packet { source: 2, dest: 3, x: 7, y: 5 },
+        ]);
+        assert!(network.is_idle());
+    }
+
+    #[test]
+    fn test_stop() {
+        struct First;
+        impl NatPolicy for First {
+            fn receive(&mut self, _packet: Packet) -> bool {
+                return false;
+            }
+            fn idle(&mut self) -> Option<Packet> {
+                panic!("Network should have stopped");
+            }
+        }
+        let mut network = Network::new(&relay(), 2);
+        assert_eq!(network.run(&mut First), Ok(NetworkResult::Stopped));
+        assert!(!network.send(Packet { source: 0, dest: 2, x: 0, y: 0 }));
+    }
+}
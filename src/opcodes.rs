@@ -0,0 +1,242 @@
+// Caller defined opcodes for IntMachine, see IntMachine::register_opcode.
+//
+// A custom instruction is laid out like the built-in ones: the op code with mode digits,
+// followed by one word per parameter. Built-in opcodes can not be replaced, and custom
+// ones are only executed at IsaLevel::Day09.
+//
+// Like the built-in instructions, custom ones should write memory only through their
+// Write parameters, those are the writes the code tracker records.
+
+use crate::intmachine::{IntcodeError, PagedMemory, Parameter, ProcessorState, Word, WordView, IO, check_params, read_params};
+use crate::wide::WordType;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+
+// Parameter modes accepted by a custom opcode
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ParameterRule {
+    // Any mode, the parameter is read
+    Read,
+    // Position or relative, the parameter is written to
+    Write,
+    // Immediate only
+    Constant,
+}
+
+// Gets the decoded parameters and returns the next ip, None halts the machine.
+// Usually that is state.ip + size(), the machine does not move the ip itself.
+pub type OpcodeFn<W = Word> = dyn Fn(&[Parameter], &mut PagedMemory<W>, &mut ProcessorState, &mut dyn IO<W>) -> Result<Option<Word>, IntcodeError> + Send + Sync;
+
+pub struct CustomOpcode<W = Word> {
+    pub op_code: Word,
+    pub name: &'static str,
+    pub params: Vec<ParameterRule>,
+    // Input words the callback receives, the machine waits for them like for Input
+    pub inputs: usize,
+    pub execute: Arc<OpcodeFn<W>>,
+}
+
+impl <W: WordType> CustomOpcode<W> {
+    pub fn new<F>(op_code: Word, name: &'static str, params: Vec<ParameterRule>, execute: F) -> CustomOpcode<W>
+        where F: Fn(&[Parameter], &mut PagedMemory<W>, &mut ProcessorState, &mut dyn IO<W>) -> Result<Option<Word>, IntcodeError> + Send + Sync + 'static {
+        return CustomOpcode { op_code, name, params, inputs: 0, execute: Arc::new(execute) };
+    }
+
+    pub fn reading_input(mut self, inputs: usize) -> CustomOpcode<W> {
+        self.inputs = inputs;
+        return self;
+    }
+
+    pub fn size(&self) -> Word {
+        return 1 + self.params.len() as Word;
+    }
+
+    // Parameters that do not fit a Word fail with Overflow, immediates included
+    pub fn decode(&self, ip: &Word, memory: &PagedMemory<W>) -> Result<Vec<Parameter>, IntcodeError> {
+        let view = WordView(memory);
+        let word = view.0.load(*ip).and_then(|v| v.to_word()).unwrap_or(0);
+        let params = read_params(ip, &view, self.params.len() as Word)?;
+        check_params(ip, &params, memory, false)?;
+        for (param, rule) in params.iter().zip(self.params.iter()) {
+            match (rule, param) {
+                (ParameterRule::Write, Parameter::Imm(_)) => return Err(IntcodeError::WriteToImmediate { ip: *ip, word }),
+                (ParameterRule::Constant, Parameter::Pos(_)) | (ParameterRule::Constant, Parameter::Rel(_)) =>
+                    return Err(IntcodeError::InvalidParameterMode { ip: *ip, word }),
+                _ => {},
+            }
+        }
+        return Ok(params);
+    }
+}
+
+// Not derived, the callback is shared and W needs no bounds
+impl <W> Clone for CustomOpcode<W> {
+    fn clone(&self) -> CustomOpcode<W> {
+        return CustomOpcode { op_code: self.op_code, name: self.name, params: self.params.clone(), inputs: self.inputs, execute: self.execute.clone() };
+    }
+}
+
+impl <W> fmt::Debug for CustomOpcode<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "CustomOpcode {{ op_code: {}, name: {:?}, params: {:?}, inputs: {} }}", self.op_code, self.name, self.params, self.inputs);
+    }
+}
+
+impl <W> PartialEq for CustomOpcode<W> {
+    fn eq(&self, other: &CustomOpcode<W>) -> bool {
+        return self.op_code == other.op_code && self.name == other.name && self.params == other.params
+            && self.inputs == other.inputs && Arc::ptr_eq(&self.execute, &other.execute);
+    }
+}
+
+impl <W> Eq for CustomOpcode<W> {}
+
+pub fn is_builtin(op_code: Word) -> bool {
+    return (1..=9).contains(&op_code) || op_code == 99;
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OpcodeTable<W = Word> {
+    opcodes: BTreeMap<Word, CustomOpcode<W>>,
+}
+
+impl <W> Default for OpcodeTable<W> {
+    fn default() -> OpcodeTable<W> {
+        return OpcodeTable { opcodes: BTreeMap::new() };
+    }
+}
+
+impl <W> OpcodeTable<W> {
+    pub fn new() -> OpcodeTable<W> {
+        return OpcodeTable::default();
+    }
+
+    // False if the op code is built in, already taken or does not fit in two digits
+    pub fn register(&mut self, opcode: CustomOpcode<W>) -> bool {
+        if opcode.op_code < 1 || opcode.op_code > 99 || is_builtin(opcode.op_code) || self.opcodes.contains_key(&opcode.op_code) {
+            return false;
+        }
+        self.opcodes.insert(opcode.op_code, opcode);
+        return true;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.opcodes.is_empty();
+    }
+
+    // The custom opcode an instruction word refers to, if any
+    pub fn get(&self, word: Word) -> Option<&CustomOpcode<W>> {
+        return self.opcodes.get(&(word % 100));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::opcodes::{CustomOpcode, ParameterRule};
+    use crate::intmachine::{IntMachine, IntcodeError, IsaLevel, Message, Status, load_parameter, store_parameter};
+    use crate::selfmod::CodeWrite;
+
+    // dbl a, [b] stores 2 * a in b
+    fn double() -> CustomOpcode {
+        return CustomOpcode::new(42, "dbl", vec![ParameterRule::Read, ParameterRule::Write], |params, memory, state, _io| {
+            let value = load_parameter(memory, state, params[0])?;
+            store_parameter(memory, state, params[1], 2 * value)?;
+            return Ok(Some(state.ip + 3));
+        });
+    }
+
+    #[test]
+    fn test_custom_opcode() {
+        // Outputs a constant twice, then halts
+        let twice = CustomOpcode::new(50, "twice", vec![ParameterRule::Constant], |params, _memory, _state, io| {
+            io.send(Message::Data(params[0].value()));
+            io.send(Message::Data(params[0].value()));
+            return Ok(None);
+        });
+        let program = vec![3,9,42,9,9,4,9,150,7,0];
+        let mut machine = IntMachine::new(&program);
+        assert!(machine.register_opcode(double()));
+        assert!(machine.register_opcode(twice));
+        machine.push_input(5);
+        assert_eq!(machine.run(), Ok(Status::Output(10)));
+        assert_eq!(machine.run(), Ok(Status::Output(7)));
+        assert_eq!(machine.run(), Ok(Status::Output(7)));
+        assert_eq!(machine.run(), Ok(Status::Halted));
+    }
+
+    #[test]
+    fn test_register() {
+        let mut machine = IntMachine::new(&vec![99]);
+        assert!(!machine.register_opcode(CustomOpcode::new(1, "add", vec![], |_, _, _, _| Ok(None))));
+        assert!(!machine.register_opcode(CustomOpcode::new(99, "hlt", vec![], |_, _, _, _| Ok(None))));
+        assert!(!machine.register_opcode(CustomOpcode::new(100, "big", vec![], |_, _, _, _| Ok(None))));
+        assert!(machine.register_opcode(double()));
+        assert!(!machine.register_opcode(double()));
+    }
+
+    #[test]
+    fn test_modes() {
+        let mut machine = IntMachine::new(&vec![1042,1,2,99]);
+        machine.register_opcode(double());
+        assert_eq!(machine.run(), Err(IntcodeError::WriteToImmediate { ip: 0, word: 1042 }));
+
+        // Unregistered op codes still fault
+        let mut machine = IntMachine::new(&vec![43,1,2,99]);
+        machine.register_opcode(double());
+        assert_eq!(machine.run(), Err(IntcodeError::UnknownInstruction { ip: 0, word: 43 }));
+    }
+
+    #[test]
+    fn test_jump_to_self() {
+        // dec [a], target decrements and outputs a, then jumps to target while a is not 0
+        let dec = CustomOpcode::new(61, "dec", vec![ParameterRule::Write, ParameterRule::Read], |params, memory, state, io| {
+            let value = load_parameter(memory, state, params[0])? - 1;
+            store_parameter(memory, state, params[0], value)?;
+            io.send(Message::Data(value));
+            return match value {
+                0 => Ok(Some(state.ip + 3)),
+                _ => Ok(Some(load_parameter(memory, state, params[1])?)),
+            }
+        });
+        let mut machine = IntMachine::new(&vec![1061,6,0,99,0,0,3]);
+        machine.register_opcode(dec);
+        assert_eq!(machine.run_to_end(), Ok(vec![2, 1, 0]));
+    }
+
+    #[test]
+    fn test_isa() {
+        let mut machine = IntMachine::new(&vec![42,0,0,99]);
+        machine.register_opcode(double());
+        machine.set_isa(IsaLevel::Day05);
+        assert_eq!(machine.run(), Err(IntcodeError::OutsideIsa { ip: 0, word: 42, level: IsaLevel::Day05 }));
+        machine.set_isa(IsaLevel::Day09);
+        assert_eq!(machine.run(), Ok(Status::Halted));
+        assert_eq!(machine.memory().load(0), Some(84));
+    }
+
+    #[test]
+    fn test_input() {
+        // sum [a] stores the sum of two input words in a, here in its own first parameter
+        let sum = CustomOpcode::new(60, "sum", vec![ParameterRule::Write], |params, memory, state, io| {
+            let mut total = 0;
+            for _ in 0..2 {
+                match io.receive() {
+                    Message::Data(data) => total += data,
+                    message => return Err(IntcodeError::UnexpectedInput { ip: state.ip, word: 60, message }),
+                }
+            }
+            store_parameter(memory, state, params[0], total)?;
+            return Ok(Some(state.ip + 2));
+        }).reading_input(2);
+        let mut machine = IntMachine::new(&vec![60,1,4,1,99]);
+        machine.register_opcode(sum);
+        machine.track_code();
+        machine.push_input(3);
+        assert_eq!(machine.run(), Ok(Status::NeedsInput));
+        assert_eq!(machine.state().ip, 0);
+        machine.push_input(4);
+        assert_eq!(machine.run(), Ok(Status::Output(7)));
+        assert_eq!(machine.code_tracker().unwrap().code_writes(), &[CodeWrite { ip: 0, address: 1, value: 7 }]);
+    }
+}
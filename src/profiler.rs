@@ -0,0 +1,129 @@
+// Execution statistics for Intcode runs, see intmachine::execute_profiled
+
+use crate::intmachine::{Instruction, ProcessorState, Word};
+use crate::intmachine::Instruction::{Input, Output};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ModeCount {
+    pub position: u64,
+    pub immediate: u64,
+    pub relative: u64,
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Profile {
+    pub instructions: u64,
+    pub by_op_code: BTreeMap<&'static str, u64>,
+    pub by_address: HashMap<Word, u64>,
+    pub by_mode: ModeCount,
+    pub inputs: u64,
+    pub outputs: u64,
+    pub max_address: Word,
+    pub relative_base_min: Word,
+    pub relative_base_max: Word,
+}
+
+impl Profile {
+    pub fn new() -> Profile {
+        return Profile::default();
+    }
+
+    // Called with the state before the instruction is executed
+    pub fn record(&mut self, instruction: &Instruction, state: &ProcessorState) {
+        self.instructions += 1;
+        *self.by_op_code.entry(instruction.mnemonic()).or_insert(0) += 1;
+        *self.by_address.entry(state.ip).or_insert(0) += 1;
+
+        let mut max = state.ip + instruction.size() - 1;
+        for p in instruction.params() {
+            match p.mode() {
+                0 => self.by_mode.position += 1,
+                1 => self.by_mode.immediate += 1,
+                _ => self.by_mode.relative += 1,
+            }
+            if let Some(address) = state.address(p) {
+                max = Word::max(max, address);
+            }
+        }
+        self.max_address = Word::max(self.max_address, max);
+
+        match instruction {
+            Input { .. } => self.inputs += 1,
+            Output { .. } => self.outputs += 1,
+            _ => {},
+        }
+    }
+
+    // Called with the state after the instruction is executed
+    pub fn record_state(&mut self, state: &ProcessorState) {
+        self.relative_base_min = Word::min(self.relative_base_min, state.relative_base);
+        self.relative_base_max = Word::max(self.relative_base_max, state.relative_base);
+    }
+
+    // The n most executed addresses
+    pub fn hot_spots(&self, n: usize) -> Vec<(Word, u64)> {
+        let mut spots: Vec<(Word, u64)> = self.by_address.iter().map(|(a, c)| (*a, *c)).collect();
+        spots.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        spots.truncate(n);
+        return spots;
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Instructions: {}", self.instructions)?;
+        for (op, count) in self.by_op_code.iter() {
+            writeln!(f, "  {:<4} {}", op, count)?;
+        }
+        writeln!(f, "Parameters: position {}, immediate {}, relative {}",
+                 self.by_mode.position, self.by_mode.immediate, self.by_mode.relative)?;
+        writeln!(f, "Input: {}, output: {}", self.inputs, self.outputs)?;
+        writeln!(f, "Max address: {}", self.max_address)?;
+        writeln!(f, "Relative base: {}..{}", self.relative_base_min, self.relative_base_max)?;
+        writeln!(f, "Hot spots:")?;
+        for (address, count) in self.hot_spots(10) {
+            writeln!(f, "  {:04} {}", address, count)?;
+        }
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::intmachine::{execute_profiled, BufferIO};
+    use crate::assembler::assemble;
+
+    #[test]
+    fn test_profile() {
+        let program = assemble("
+                arb  100
+                in   [rb-1]
+        loop:   out  [count]
+                add  [count], 1, [count]
+                lt   [count], 3, [more]
+                jt   [more], loop
+                hlt
+        count:  data 0
+        more:   data 0
+        ").unwrap();
+        let mut io = BufferIO::new(vec![5]);
+        let (memory, profile) = execute_profiled(&program, &mut io).unwrap();
+        assert_eq!(memory.load(99), Some(5));
+        assert_eq!(io.output, vec![0, 1, 2]);
+
+        assert_eq!(profile.instructions, 2 + 3 * 4 + 1);
+        assert_eq!(profile.by_op_code.get("out"), Some(&3));
+        assert_eq!(profile.by_op_code.get("hlt"), Some(&1));
+        assert_eq!(profile.by_address.get(&4), Some(&3));
+        assert_eq!(profile.hot_spots(1), vec![(4, 3)]);
+        assert_eq!(profile.by_mode.relative, 1);
+        assert_eq!(profile.by_mode.immediate, 1 + 3 * 3);
+        assert_eq!(profile.by_mode.position, 3 * 6);
+        assert_eq!(profile.inputs, 1);
+        assert_eq!(profile.outputs, 3);
+        assert_eq!(profile.max_address, 99);
+        assert_eq!((profile.relative_base_min, profile.relative_base_max), (0, 100));
+    }
+}
@@ -0,0 +1,132 @@
+// Self modifying code detection, see IntMachine::track_code.
+//
+// Records every address that was executed as part of an instruction and every address that
+// was written. Writes to cells that were already decoded as code are kept as CodeWrite, with
+// the ip of the instruction doing the write.
+
+use crate::intmachine::Word;
+use std::collections::{BTreeSet, HashSet};
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct CodeWrite<W = Word> {
+    pub ip: Word,
+    pub address: Word,
+    pub value: W,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CodeTracker<W = Word> {
+    starts: BTreeSet<Word>,
+    executed: HashSet<Word>,
+    written: HashSet<Word>,
+    writes: Vec<CodeWrite<W>>,
+}
+
+impl <W> Default for CodeTracker<W> {
+    fn default() -> CodeTracker<W> {
+        return CodeTracker { starts: BTreeSet::new(), executed: HashSet::new(), written: HashSet::new(), writes: vec![] };
+    }
+}
+
+impl <W> CodeTracker<W> {
+    pub fn new() -> CodeTracker<W> {
+        return CodeTracker::default();
+    }
+
+    // Called for every instruction before it is executed, size includes the op code
+    pub fn record_instruction(&mut self, ip: Word, size: Word) {
+        self.starts.insert(ip);
+        self.executed.extend(ip..ip + size);
+    }
+
+    // Called after an instruction at ip stored value at address
+    pub fn record_write(&mut self, ip: Word, address: Word, value: W) {
+        self.written.insert(address);
+        if self.executed.contains(&address) {
+            self.writes.push(CodeWrite { ip, address, value });
+        }
+    }
+
+    pub fn is_executed(&self, address: Word) -> bool {
+        return self.executed.contains(&address);
+    }
+
+    pub fn is_written(&self, address: Word) -> bool {
+        return self.written.contains(&address);
+    }
+
+    // Addresses of all executed instructions, usable as disassembler entry points
+    pub fn instruction_starts(&self) -> Vec<Word> {
+        return self.starts.iter().cloned().collect();
+    }
+
+    // Addresses that were both executed and written, in either order
+    pub fn modified_code(&self) -> Vec<Word> {
+        let mut modified: Vec<Word> = self.executed.intersection(&self.written).cloned().collect();
+        modified.sort();
+        return modified;
+    }
+
+    // Writes into code that had been executed before
+    pub fn code_writes(&self) -> &[CodeWrite<W>] {
+        return &self.writes;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::selfmod::CodeWrite;
+    use crate::intmachine::{IntMachine, Status};
+    use crate::disassembler::{disassemble_from, Entry};
+    use crate::assembler::assemble;
+
+    #[test]
+    fn test_code_writes() {
+        // Outputs 1, 2 and 3, by patching the immediate operand of the out instruction
+        let program = assemble("
+        loop:   out  1
+                add  [loop+1], 1, [loop+1]
+                eq   [loop+1], 4, [done]
+                jf   [done], loop
+                hlt
+        done:   data 0
+        ").unwrap();
+        let mut machine = IntMachine::new(&program);
+        machine.track_code();
+        assert_eq!(machine.run_to_end(), Ok(vec![1, 2, 3]));
+
+        let tracker = machine.code_tracker().unwrap();
+        assert_eq!(tracker.modified_code(), vec![1]);
+        assert_eq!(tracker.code_writes(), &[
+            CodeWrite { ip: 2, address: 1, value: 2 },
+            CodeWrite { ip: 2, address: 1, value: 3 },
+            CodeWrite { ip: 2, address: 1, value: 4 },
+        ]);
+        assert!(tracker.is_written(14));
+        assert!(!tracker.is_executed(14));
+        assert_eq!(tracker.instruction_starts(), vec![0, 2, 6, 10, 13]);
+    }
+
+    #[test]
+    fn test_written_before_executed() {
+        // The input is stored in the condition of the jump that follows, before it is decoded
+        let program = vec![3,3,1105,-1,9,1101,0,0,12,4,12,99,1];
+        let mut machine = IntMachine::new(&program);
+        machine.track_code();
+        machine.push_input(0);
+        assert_eq!(machine.run(), Ok(Status::Output(0)));
+        let tracker = machine.code_tracker().unwrap();
+        assert_eq!(tracker.modified_code(), vec![3]);
+        assert_eq!(tracker.code_writes(), &[]);
+
+        // Untracked machines have no tracker
+        assert_eq!(IntMachine::new(&program).code_tracker(), None);
+
+        let listing = disassemble_from(&program, &tracker.instruction_starts());
+        assert_eq!(listing.entries[1].address(), 2);
+        match listing.entries[1] {
+            Entry::Code { .. } => {},
+            _ => panic!("Expected code at 2"),
+        }
+    }
+}
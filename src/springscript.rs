@@ -0,0 +1,746 @@
+// Springscript compiler, interpreter and hull simulator for the day21 springdroid.
+//
+// Formulas use the sensors A-I, ! for not, & for and, | for or, parentheses and the constants
+// 0 and 1, for example "(!A | !B | !C) & D". The formula is reduced to a minimal sum of products
+// and product of sums, and the cheaper of the two is emitted using T for each term and J for the
+// result. Both registers are false when the script starts.
+//
+// Hulls are written like the droid draws them, # is ground and . a hole, starting at the tile
+// the droid stands on. The hull continues with ground past the end of the string.
+//
+// search() looks for a script that makes it across a set of hulls without a formula, by
+// evolving random scripts and keeping the ones that get furthest.
+
+use rand::Rng;
+use std::collections::BTreeSet;
+use std::fmt;
+
+pub const MAX_INSTRUCTIONS: usize = 15;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Mode {
+    // Sensors A to D
+    Walk,
+    // Sensors A to I
+    Run,
+}
+
+impl Mode {
+    pub fn sensors(&self) -> usize {
+        return match self {
+            Mode::Walk => 4,
+            Mode::Run => 9,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Register {
+    // Ground sensor, 0 is A
+    Sensor(u8),
+    T,
+    J,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Op {
+    And,
+    Or,
+    Not,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Instruction {
+    pub op: Op,
+    pub src: Register,
+    pub dst: Register,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Program {
+    pub instructions: Vec<Instruction>,
+    pub mode: Mode,
+}
+
+impl Program {
+    // Whether the droid jumps, sensor s is bit s of sensors
+    pub fn jumps(&self, sensors: u16) -> bool {
+        let (mut t, mut j) = (false, false);
+        for Instruction { op, src, dst } in self.instructions.iter() {
+            let x = match src {
+                Register::Sensor(s) => sensors & (1 << s) != 0,
+                Register::T => t,
+                Register::J => j,
+            };
+            let y = if *dst == Register::T { &mut t } else { &mut j };
+            *y = match op {
+                Op::And => x && *y,
+                Op::Or => x || *y,
+                Op::Not => !x,
+            };
+        }
+        return j;
+    }
+
+    // Walks the droid over the hull
+    pub fn simulate(&self, hull: &str) -> Outcome {
+        let ground: Vec<bool> = hull.chars().map(|c| c == '#').collect();
+        let is_ground = |p: usize| p >= ground.len() || ground[p];
+        let mut position = 0;
+        let mut jumps = vec![];
+        while position < ground.len() {
+            let sensors = (0..self.mode.sensors())
+                .filter(|s| is_ground(position + s + 1))
+                .fold(0u16, |acc, s| acc | (1 << s));
+            if self.jumps(sensors) {
+                jumps.push(position);
+                position += 4;
+            } else {
+                position += 1;
+            }
+            if !is_ground(position) {
+                return Outcome::Fell { position, jumps };
+            }
+        }
+        return Outcome::Survived { jumps };
+    }
+
+    // Hulls the droid falls on, with the outcome
+    pub fn check<'a>(&self, hulls: &'a [String]) -> Vec<(&'a str, Outcome)> {
+        return hulls.iter()
+            .map(|hull| (hull.as_str(), self.simulate(hull)))
+            .filter(|(_, outcome)| !outcome.survived())
+            .collect();
+    }
+
+    // The lines to send to the springdroid, ending with WALK or RUN
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines: Vec<String> = self.instructions.iter().map(|i| i.to_string()).collect();
+        lines.push(match self.mode {
+            Mode::Walk => "WALK".to_string(),
+            Mode::Run => "RUN".to_string(),
+        });
+        return lines;
+    }
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            Register::Sensor(s) => write!(f, "{}", (b'A' + s) as char),
+            Register::T => write!(f, "T"),
+            Register::J => write!(f, "J"),
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let op = match self.op {
+            Op::And => "AND",
+            Op::Or => "OR",
+            Op::Not => "NOT",
+        };
+        return write!(f, "{} {} {}", op, self.src, self.dst);
+    }
+}
+
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for line in self.lines() {
+            writeln!(f, "{}", line)?;
+        }
+        return Ok(());
+    }
+}
+
+// Where the droid jumped, and where it fell in
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Outcome {
+    Survived { jumps: Vec<usize> },
+    Fell { position: usize, jumps: Vec<usize> },
+}
+
+impl Outcome {
+    pub fn survived(&self) -> bool {
+        return match self {
+            Outcome::Survived { .. } => true,
+            Outcome::Fell { .. } => false,
+        }
+    }
+}
+
+// The hull from the report the droid prints when it does not make it across
+pub fn hull_from_report(report: &str) -> Option<String> {
+    return report.lines()
+        .skip_while(|l| !l.starts_with("Didn't make it across"))
+        .find(|l| l.contains('#') && l.chars().all(|c| c == '#' || c == '.'))
+        .map(|l| l.to_string());
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum CompileError {
+    Parse { position: usize, message: String },
+    // The sensor can not be used in the mode
+    UnavailableSensor { sensor: char, mode: Mode },
+    // Even the shortest script is too long
+    TooLong { instructions: usize },
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            CompileError::Parse { position, message } => write!(f, "{} at position {}", message, position),
+            CompileError::UnavailableSensor { sensor, mode } => write!(f, "Sensor {} is not available in {:?} mode", sensor, mode),
+            CompileError::TooLong { instructions } =>
+                write!(f, "Needs {} instructions, only {} are allowed", instructions, MAX_INSTRUCTIONS),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Expr {
+    Const(bool),
+    Sensor(u8),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    // Sensor s is bit s of sensors
+    pub fn eval(&self, sensors: u16) -> bool {
+        return match self {
+            Expr::Const(v) => *v,
+            Expr::Sensor(s) => sensors & (1 << s) != 0,
+            Expr::Not(e) => !e.eval(sensors),
+            Expr::And(a, b) => a.eval(sensors) && b.eval(sensors),
+            Expr::Or(a, b) => a.eval(sensors) || b.eval(sensors),
+        }
+    }
+
+    fn sensors(&self, used: &mut BTreeSet<u8>) {
+        match self {
+            Expr::Const(_) => {},
+            Expr::Sensor(s) => { used.insert(*s); },
+            Expr::Not(e) => e.sensors(used),
+            Expr::And(a, b) | Expr::Or(a, b) => {
+                a.sensors(used);
+                b.sensors(used);
+            },
+        }
+    }
+}
+
+// Recursive descent, | binds weakest, then &, then !
+struct Parser<'a> {
+    chars: Vec<(usize, char)>,
+    pos: usize,
+    text: &'a str,
+}
+
+impl <'a> Parser<'a> {
+    fn error<T>(&self, message: &str) -> Result<T, CompileError> {
+        let position = self.chars.get(self.pos).map_or(self.text.len(), |(p, _)| *p);
+        return Err(CompileError::Parse { position, message: message.to_string() });
+    }
+
+    fn peek(&self) -> Option<char> {
+        return self.chars.get(self.pos).map(|(_, c)| *c);
+    }
+
+    fn or(&mut self) -> Result<Expr, CompileError> {
+        let mut expr = self.and()?;
+        while self.peek() == Some('|') {
+            self.pos += 1;
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        return Ok(expr);
+    }
+
+    fn and(&mut self) -> Result<Expr, CompileError> {
+        let mut expr = self.not()?;
+        while self.peek() == Some('&') {
+            self.pos += 1;
+            expr = Expr::And(Box::new(expr), Box::new(self.not()?));
+        }
+        return Ok(expr);
+    }
+
+    fn not(&mut self) -> Result<Expr, CompileError> {
+        let c = match self.peek() {
+            Some(c) => c,
+            None => return self.error("Unexpected end of formula"),
+        };
+        self.pos += 1;
+        return match c {
+            '!' => Ok(Expr::Not(Box::new(self.not()?))),
+            '(' => {
+                let expr = self.or()?;
+                if self.peek() != Some(')') {
+                    return self.error("Expected )");
+                }
+                self.pos += 1;
+                Ok(expr)
+            },
+            '0' => Ok(Expr::Const(false)),
+            '1' => Ok(Expr::Const(true)),
+            'A'..='I' => Ok(Expr::Sensor(c as u8 - b'A')),
+            _ => {
+                self.pos -= 1;
+                self.error(&format!("Unexpected {}", c))
+            },
+        }
+    }
+}
+
+pub fn parse(formula: &str) -> Result<Expr, CompileError> {
+    let chars = formula.char_indices()
+        .filter(|(_, c)| !c.is_whitespace())
+        .map(|(i, c)| (i, c.to_ascii_uppercase()))
+        .collect();
+    let mut parser = Parser { chars, pos: 0, text: formula };
+    let expr = parser.or()?;
+    if parser.pos < parser.chars.len() {
+        return parser.error("Expected end of formula");
+    }
+    return Ok(expr);
+}
+
+// A product of sensors, bits in mask are left out, the rest must equal value
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+struct Implicant {
+    value: u16,
+    mask: u16,
+}
+
+impl Implicant {
+    fn covers(&self, minterm: u16) -> bool {
+        return minterm & !self.mask == self.value;
+    }
+
+    // (sensor, positive) for each sensor in the product
+    fn literals(&self, sensors: &[u8]) -> Vec<(u8, bool)> {
+        return sensors.iter().enumerate()
+            .filter(|(bit, _)| self.mask & (1 << bit) == 0)
+            .map(|(bit, s)| (*s, self.value & (1 << bit) != 0))
+            .collect();
+    }
+}
+
+// Minimal sum of products with Quine-McCluskey, the cover is chosen greedily after the essential implicants
+fn minimize(minterms: &[u16], bits: usize) -> Vec<Implicant> {
+    let mut primes: BTreeSet<Implicant> = BTreeSet::new();
+    let mut current: BTreeSet<Implicant> = minterms.iter().map(|m| Implicant { value: *m, mask: 0 }).collect();
+    while !current.is_empty() {
+        let mut next = BTreeSet::new();
+        let mut combined = BTreeSet::new();
+        for a in current.iter() {
+            for bit in 0..bits {
+                let b = Implicant { value: a.value ^ (1 << bit), mask: a.mask };
+                if a.mask & (1 << bit) == 0 && current.contains(&b) {
+                    next.insert(Implicant { value: a.value & !(1 << bit), mask: a.mask | (1 << bit) });
+                    combined.insert(*a);
+                    combined.insert(b);
+                }
+            }
+        }
+        primes.extend(current.difference(&combined));
+        current = next;
+    }
+
+    let mut uncovered: BTreeSet<u16> = minterms.iter().cloned().collect();
+    let mut cover = vec![];
+    for m in minterms {
+        let covering: Vec<&Implicant> = primes.iter().filter(|p| p.covers(*m)).collect();
+        if covering.len() == 1 && !cover.contains(covering[0]) {
+            cover.push(*covering[0]);
+        }
+    }
+    uncovered.retain(|m| !cover.iter().any(|p| p.covers(*m)));
+    while !uncovered.is_empty() {
+        let best = *primes.iter()
+            .max_by_key(|p| (uncovered.iter().filter(|m| p.covers(**m)).count(), p.mask.count_ones()))
+            .unwrap();
+        uncovered.retain(|m| !best.covers(*m));
+        cover.push(best);
+    }
+    return cover;
+}
+
+fn emit(code: &mut Vec<Instruction>, op: Op, src: Register, dst: Register) {
+    code.push(Instruction { op, src, dst });
+}
+
+// Computes a chain of literals joined by op into register r, clean if r is still false
+fn chain(code: &mut Vec<Instruction>, op: Op, literals: &[(u8, bool)], r: Register, clean: bool) {
+    for (i, (sensor, positive)) in literals.iter().enumerate() {
+        let s = Register::Sensor(*sensor);
+        match (i, positive) {
+            (0, true) if clean => emit(code, Op::Or, s, r),
+            (0, true) => {
+                emit(code, Op::Not, s, r);
+                emit(code, Op::Not, r, r);
+            },
+            (0, false) => emit(code, Op::Not, s, r),
+            (_, true) => emit(code, op, s, r),
+            (_, false) => {
+                // r op !s is !(!r op' s)
+                let dual = if op == Op::And { Op::Or } else { Op::And };
+                emit(code, Op::Not, r, r);
+                emit(code, dual, s, r);
+                emit(code, Op::Not, r, r);
+            },
+        }
+    }
+}
+
+// Shortest code for a chain, directly or as the negation of the dual chain
+fn term(op: Op, literals: &[(u8, bool)], r: Register, clean: bool) -> Vec<Instruction> {
+    let mut sorted = literals.to_vec();
+    // Starting with a negated literal never costs more
+    sorted.sort_by_key(|(s, positive)| (*positive, *s));
+    let mut direct = vec![];
+    chain(&mut direct, op, &sorted, r, clean);
+
+    let dual = if op == Op::And { Op::Or } else { Op::And };
+    let mut complement: Vec<(u8, bool)> = literals.iter().map(|(s, p)| (*s, !p)).collect();
+    complement.sort_by_key(|(s, positive)| (*positive, *s));
+    let mut negated = vec![];
+    chain(&mut negated, dual, &complement, r, clean);
+    emit(&mut negated, Op::Not, r, r);
+
+    return if negated.len() < direct.len() { negated } else { direct };
+}
+
+// J = terms joined by outer, each term is its literals joined by inner
+fn combine(outer: Op, inner: Op, terms: &[Vec<(u8, bool)>], first: usize) -> Vec<Instruction> {
+    let mut code = term(inner, &terms[first], Register::J, true);
+    let mut t_clean = true;
+    for (i, literals) in terms.iter().enumerate() {
+        if i == first {
+            continue;
+        }
+        if literals.len() == 1 && literals[0].1 {
+            emit(&mut code, outer, Register::Sensor(literals[0].0), Register::J);
+            continue;
+        }
+        code.extend(term(inner, literals, Register::T, t_clean));
+        t_clean = false;
+        emit(&mut code, outer, Register::T, Register::J);
+    }
+    return code;
+}
+
+pub fn compile_expr(expr: &Expr, mode: Mode) -> Result<Program, CompileError> {
+    let mut used = BTreeSet::new();
+    expr.sensors(&mut used);
+    if let Some(s) = used.iter().find(|s| **s as usize >= mode.sensors()) {
+        return Err(CompileError::UnavailableSensor { sensor: (b'A' + s) as char, mode });
+    }
+    let sensors: Vec<u8> = used.into_iter().collect();
+    let assignment = |row: u16| {
+        return sensors.iter().enumerate()
+            .filter(|(bit, _)| row & (1 << bit) != 0)
+            .fold(0u16, |acc, (_, s)| acc | (1 << s));
+    };
+    let rows = 1u16 << sensors.len();
+    let (ones, zeros): (Vec<u16>, Vec<u16>) = (0..rows).partition(|row| expr.eval(assignment(*row)));
+
+    let instructions = if zeros.is_empty() {
+        // J = !T
+        vec![Instruction { op: Op::Not, src: Register::T, dst: Register::J }]
+    } else if ones.is_empty() {
+        vec![]
+    } else {
+        let products: Vec<Vec<(u8, bool)>> = minimize(&ones, sensors.len()).iter().map(|i| i.literals(&sensors)).collect();
+        // The product of sums is the complement of the sum of products of the zeros
+        let sums: Vec<Vec<(u8, bool)>> = minimize(&zeros, sensors.len()).iter()
+            .map(|i| i.literals(&sensors).iter().map(|(s, p)| (*s, !p)).collect())
+            .collect();
+        let candidates = (0..products.len()).map(|first| combine(Op::Or, Op::And, &products, first))
+            .chain((0..sums.len()).map(|first| combine(Op::And, Op::Or, &sums, first)));
+        candidates.min_by_key(|code| code.len()).unwrap()
+    };
+    if instructions.len() > MAX_INSTRUCTIONS {
+        return Err(CompileError::TooLong { instructions: instructions.len() });
+    }
+    return Ok(Program { instructions, mode });
+}
+
+// Reads springscript as sent to the droid, one instruction per line and WALK or RUN last
+pub fn parse_script(script: &str) -> Result<Program, CompileError> {
+    let mut instructions = vec![];
+    let mut position = 0;
+    for line in script.lines() {
+        let error = |message: &str| Err(CompileError::Parse { position, message: message.to_string() });
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let register = |word: &str| match word {
+            "T" => Some(Register::T),
+            "J" => Some(Register::J),
+            _ if word.len() == 1 && ("A"..="I").contains(&word) => Some(Register::Sensor(word.as_bytes()[0] - b'A')),
+            _ => None,
+        };
+        match words.as_slice() {
+            [] => {},
+            ["WALK"] | ["RUN"] => {
+                let mode = if words[0] == "WALK" { Mode::Walk } else { Mode::Run };
+                for i in instructions.iter() {
+                    if let Instruction { src: Register::Sensor(s), .. } = i {
+                        if *s as usize >= mode.sensors() {
+                            return Err(CompileError::UnavailableSensor { sensor: (b'A' + s) as char, mode });
+                        }
+                    }
+                }
+                if instructions.len() > MAX_INSTRUCTIONS {
+                    return Err(CompileError::TooLong { instructions: instructions.len() });
+                }
+                return Ok(Program { instructions, mode });
+            },
+            [op, src, dst] => {
+                let op = match *op {
+                    "AND" => Op::And,
+                    "OR" => Op::Or,
+                    "NOT" => Op::Not,
+                    _ => return error("Unknown instruction"),
+                };
+                let (src, dst) = match (register(src), register(dst)) {
+                    (Some(src), Some(dst)) if dst == Register::T || dst == Register::J => (src, dst),
+                    _ => return error("Invalid register"),
+                };
+                instructions.push(Instruction { op, src, dst });
+            },
+            _ => return error("Expected an instruction"),
+        }
+        position += line.len() + 1;
+    }
+    return Err(CompileError::Parse { position: script.len(), message: "Expected WALK or RUN".to_string() });
+}
+
+pub fn compile(formula: &str, mode: Mode) -> Result<Program, CompileError> {
+    return compile_expr(&parse(formula)?, mode);
+}
+
+// Scripts per generation, and how many of the best survive into the next one
+const POPULATION: usize = 200;
+const ELITE: usize = 20;
+const STALL: usize = 50;
+
+fn random_instruction<R: Rng>(mode: Mode, rng: &mut R) -> Instruction {
+    let op = [Op::And, Op::Or, Op::Not][rng.gen_range(0, 3)];
+    let src = match rng.gen_range(0, mode.sensors() + 2) {
+        s if s < mode.sensors() => Register::Sensor(s as u8),
+        s if s == mode.sensors() => Register::T,
+        _ => Register::J,
+    };
+    let dst = if rng.gen() { Register::T } else { Register::J };
+    return Instruction { op, src, dst };
+}
+
+fn mutate<R: Rng>(program: &Program, rng: &mut R) -> Program {
+    let mut instructions = program.instructions.clone();
+    let n = instructions.len();
+    match rng.gen_range(0, 4) {
+        0 if n < MAX_INSTRUCTIONS => instructions.insert(rng.gen_range(0, n + 1), random_instruction(program.mode, rng)),
+        1 if n > 0 => { instructions.remove(rng.gen_range(0, n)); },
+        2 if n > 1 => instructions.swap(rng.gen_range(0, n), rng.gen_range(0, n)),
+        _ if n > 0 => instructions[rng.gen_range(0, n)] = random_instruction(program.mode, rng),
+        _ => instructions.push(random_instruction(program.mode, rng)),
+    }
+    return Program { instructions, mode: program.mode };
+}
+
+// Hulls crossed, then the distance covered before falling. Length is left to shorten(),
+// preferring short scripts here makes the population lose the parts it needs later.
+fn fitness(program: &Program, hulls: &[String]) -> (usize, usize) {
+    let (mut crossed, mut distance) = (0, 0);
+    for hull in hulls {
+        match program.simulate(hull) {
+            Outcome::Survived { .. } => {
+                crossed += 1;
+                distance += hull.len();
+            },
+            Outcome::Fell { position, .. } => distance += position,
+        }
+    }
+    return (crossed, distance);
+}
+
+// The start of one script followed by the end of another
+fn crossover<R: Rng>(a: &Program, b: &Program, rng: &mut R) -> Program {
+    let i = rng.gen_range(0, a.instructions.len() + 1);
+    let j = rng.gen_range(0, b.instructions.len() + 1);
+    let mut instructions: Vec<Instruction> = a.instructions[..i].iter().chain(b.instructions[j..].iter()).cloned().collect();
+    instructions.truncate(MAX_INSTRUCTIONS);
+    return Program { instructions, mode: a.mode };
+}
+
+// Searches for a script that makes it across every hull, None if none is found in time.
+// The population starts over when the best script has not improved for STALL generations.
+pub fn search<R: Rng>(mode: Mode, hulls: &[String], generations: usize, rng: &mut R) -> Option<Program> {
+    let empty = Program { instructions: vec![], mode };
+    let mut population = vec![];
+    let mut best = (0, 0);
+    let mut stalled = 0;
+    for _ in 0..generations {
+        if stalled == STALL || population.is_empty() {
+            population = (0..POPULATION).map(|_| mutate(&empty, rng)).collect();
+            population.push(empty.clone());
+            best = (0, 0);
+            stalled = 0;
+        }
+        let mut scored: Vec<_> = population.into_iter().map(|p| (fitness(&p, hulls), p)).collect();
+        scored.sort_by_key(|(f, _)| std::cmp::Reverse(*f));
+        if scored[0].0 .0 == hulls.len() {
+            return Some(shorten(&scored[0].1, hulls));
+        }
+        if scored[0].0 > best {
+            best = scored[0].0;
+            stalled = 0;
+        } else {
+            stalled += 1;
+        }
+        let mut elite: Vec<Program> = vec![];
+        for (_, program) in scored {
+            if elite.len() == ELITE {
+                break;
+            }
+            if !elite.contains(&program) {
+                elite.push(program);
+            }
+        }
+        population = elite.clone();
+        while population.len() < POPULATION {
+            let parent = &elite[rng.gen_range(0, elite.len())];
+            let mut child = if rng.gen_range(0, 4) == 0 {
+                crossover(parent, &elite[rng.gen_range(0, elite.len())], rng)
+            } else {
+                parent.clone()
+            };
+            for _ in 0..rng.gen_range(1, 4) {
+                child = mutate(&child, rng);
+            }
+            population.push(child);
+        }
+    }
+    return None;
+}
+
+// Drops instructions as long as the script still makes it across every hull
+fn shorten(program: &Program, hulls: &[String]) -> Program {
+    let mut program = program.clone();
+    let mut i = 0;
+    while i < program.instructions.len() {
+        let mut shorter = program.clone();
+        shorter.instructions.remove(i);
+        if shorter.check(hulls).is_empty() {
+            program = shorter;
+        } else {
+            i += 1;
+        }
+    }
+    return program;
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::springscript::{compile, parse, parse_script, search, hull_from_report, CompileError, Mode, Outcome};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_compile() {
+        let formulas = [
+            "(!A | !B | !C) & D",
+            "(!A | !B | !C) & D & (E | H)",
+            "!A | (!B & D & H) | (!C & D & (E | H))",
+            "A & !A",
+            "A | !A",
+            "(A & !B) | (!A & B) | (C & !D & E) | (!F & G & !H & I)",
+        ];
+        for formula in formulas.iter() {
+            let expr = parse(formula).unwrap();
+            let program = match compile(formula, Mode::Run) {
+                Ok(program) => program,
+                Err(CompileError::TooLong { .. }) => continue,
+                Err(error) => panic!("{}: {}", formula, error),
+            };
+            for sensors in 0..512 {
+                assert_eq!(program.jumps(sensors), expr.eval(sensors), "{} with {:09b}", formula, sensors);
+            }
+        }
+        let program = compile("(!A | !B | !C) & D", Mode::Walk).unwrap();
+        assert!(program.instructions.len() <= 6);
+        assert_eq!(program.lines().last(), Some(&"WALK".to_string()));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(compile("A & E", Mode::Walk), Err(CompileError::UnavailableSensor { sensor: 'E', mode: Mode::Walk }));
+        assert_eq!(parse("A & (B | C"), Err(CompileError::Parse { position: 10, message: "Expected )".to_string() }));
+        assert_eq!(parse("A + B"), Err(CompileError::Parse { position: 2, message: "Expected end of formula".to_string() }));
+        assert_eq!(parse("A & "), Err(CompileError::Parse { position: 4, message: "Unexpected end of formula".to_string() }));
+        match compile("(A & !B) | (!A & B) | (C & !D) | (!C & D) | (E & !F) | (!E & F)", Mode::Run) {
+            Err(CompileError::TooLong { instructions }) => assert!(instructions > 15),
+            other => panic!("Expected TooLong, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_simulate() {
+        let program = parse_script("NOT A J\nNOT B T\nOR T J\nNOT C T\nOR T J\nAND D J\nWALK\n").unwrap();
+        let compiled = compile("(!A | !B | !C) & D", Mode::Walk).unwrap();
+        assert_eq!(parse_script(&compiled.to_string()), Ok(compiled));
+        assert_eq!(program.simulate("#####.###########"), Outcome::Survived { jumps: vec![2] });
+        assert_eq!(program.simulate("#####..#.########"), Outcome::Survived { jumps: vec![3, 7] });
+        // Jumps as early as it can and lands where it can not jump again
+        assert_eq!(program.simulate("###.#..#.#"), Outcome::Fell { position: 5, jumps: vec![0] });
+
+        let run = compile("(!A | !B | !C) & D & (E | H)", Mode::Run).unwrap();
+        let hulls = vec!["#####.#.##..#.###".to_string(), "#####.##.##.#.###".to_string()];
+        assert_eq!(program.check(&hulls).len(), 1);
+        assert_eq!(program.check(&hulls)[0].0, "#####.#.##..#.###");
+        assert_eq!(run.check(&hulls), vec![]);
+    }
+
+    #[test]
+    fn test_report() {
+        let report = "Walking...\n\n\nDidn't make it across:\n\n.................\n.................\n\
+                      @................\n#####..#.########\n\n.................\n";
+        assert_eq!(hull_from_report(report), Some("#####..#.########".to_string()));
+        assert_eq!(hull_from_report("Walking...\n"), None);
+    }
+
+    #[test]
+    fn test_parse_script() {
+        assert_eq!(parse_script("NOT E J\nWALK\n"), Err(CompileError::UnavailableSensor { sensor: 'E', mode: Mode::Walk }));
+        assert_eq!(parse_script("NOT A J\nAND J A\nRUN\n"), Err(CompileError::Parse { position: 8, message: "Invalid register".to_string() }));
+        assert_eq!(parse_script("NOT A J\n"), Err(CompileError::Parse { position: 8, message: "Expected WALK or RUN".to_string() }));
+        assert_eq!(parse_script("XOR A J\nRUN"), Err(CompileError::Parse { position: 0, message: "Unknown instruction".to_string() }));
+    }
+
+    #[test]
+    fn test_search() {
+        let hulls: Vec<String> = ["#####.###########", "#####..#.########", "#####...#########", "#####.#.##..#.###"]
+            .iter().map(|h| h.to_string()).collect();
+        let mut rng = StdRng::seed_from_u64(21);
+        let program = search(Mode::Run, &hulls, 1000, &mut rng).unwrap();
+        assert_eq!(program.check(&hulls), vec![]);
+        assert!(program.instructions.len() <= 15);
+
+        // The hulls the day 21 droid falls on while searching for a Run script
+        let hulls: Vec<String> = [
+            "#####.###########", "#####..#.########", "#####.###.#..####", "#####.###...#.###",
+            "#####.#.##.#.####", "#####.#..##.#####", "#####.###..#..###", "#####.##.#.#.####",
+            "#####..####.#.###", "#####.####.#..###", "#####.#.#.#...###", "#####...##.##.###",
+            "#####.#.###.#.###",
+        ].iter().map(|h| h.to_string()).collect();
+        let program = search(Mode::Run, &hulls, 2000, &mut StdRng::seed_from_u64(21)).unwrap();
+        assert_eq!(program.check(&hulls), vec![]);
+        assert!(program.instructions.len() <= 15);
+
+        // Nothing gets across two holes in a row
+        assert_eq!(search(Mode::Walk, &["#####....#".to_string()], 20, &mut rng), None);
+        assert_eq!(search(Mode::Walk, &[], 20, &mut rng).map(|p| p.instructions.len()), Some(0));
+    }
+}
@@ -0,0 +1,361 @@
+// Execution traces for Intcode runs, see intmachine::execute_traced
+//
+// A trace holds one entry per executed instruction. It can be saved in a compact binary
+// form, replayed on top of the initial memory without a machine, or checked against a
+// fresh run of the machine with verify().
+
+use crate::intmachine::{Instruction, IntcodeError, Memory, PagedMemory, ProcessorState, Word, IO, BufferIO, load_program, execute_step, decode_instruction, try_decode_instruction};
+use crate::intmachine::Instruction::{Input, Output};
+use crate::wide::OverflowPolicy;
+use std::fmt;
+use std::fs;
+use std::io;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum IoEvent {
+    Input(Word),
+    Output(Word),
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TraceEntry {
+    pub state: ProcessorState,
+    pub instruction: Instruction,
+    pub loaded: Vec<Word>,
+    pub write: Option<(Word, Word)>,
+    pub io: Option<IoEvent>,
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Trace {
+    pub entries: Vec<TraceEntry>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Divergence {
+    pub step: usize,
+    pub expected: Option<TraceEntry>,
+    pub actual: Option<TraceEntry>,
+}
+
+// Values of all parameters that are read, in order
+fn operands(memory: &PagedMemory, state: &ProcessorState, instruction: &Instruction) -> Vec<Word> {
+    let mut params = instruction.params();
+    if instruction.destination().is_some() {
+        params.pop();
+    }
+    return params.iter().map(|p| match state.address(*p) {
+        Some(address) => memory.load(address).unwrap_or(0),
+        None => p.value(),
+    }).collect();
+}
+
+fn pending_entry(memory: &PagedMemory, state: &ProcessorState, instruction: Instruction) -> TraceEntry {
+    return TraceEntry {
+        state: *state,
+        loaded: operands(memory, state, &instruction),
+        instruction,
+        write: None,
+        io: None,
+    };
+}
+
+// Executes one instruction and returns what happened, and if the machine halted
+pub(crate) fn traced_step(memory: &mut PagedMemory, state: &mut ProcessorState, io: &mut dyn IO) -> Result<(TraceEntry, bool), IntcodeError> {
+    let instruction = decode_instruction(&state.ip, &*memory)?;
+    let mut entry = pending_entry(memory, state, instruction);
+    let destination = instruction.destination().and_then(|dst| state.address(dst));
+
+    let halted = execute_step(memory, state, io, OverflowPolicy::default())?;
+
+    if let Some(address) = destination {
+        let value = memory.load(address).unwrap_or(0);
+        entry.write = Some((address, value));
+        if let Input { .. } = instruction {
+            entry.io = Some(IoEvent::Input(value));
+        }
+    }
+    if let Output { .. } = instruction {
+        entry.io = Some(IoEvent::Output(entry.loaded[0]));
+    }
+    return Ok((entry, halted));
+}
+
+impl Trace {
+    pub fn new() -> Trace {
+        return Trace::default();
+    }
+
+    pub fn inputs(&self) -> Vec<Word> {
+        return self.entries.iter().filter_map(|e| match e.io {
+            Some(IoEvent::Input(v)) => Some(v),
+            _ => None,
+        }).collect();
+    }
+
+    pub fn outputs(&self) -> Vec<Word> {
+        return self.entries.iter().filter_map(|e| match e.io {
+            Some(IoEvent::Output(v)) => Some(v),
+            _ => None,
+        }).collect();
+    }
+
+    // Memory after the first steps entries, without running the machine
+    pub fn replay(&self, initial: &Memory, steps: usize) -> PagedMemory {
+        let mut memory = load_program(initial);
+        for entry in self.entries.iter().take(steps) {
+            if let Some((address, value)) = entry.write {
+                memory.store(address, value);
+            }
+        }
+        return memory;
+    }
+
+    pub fn save(&self, filename: &str) -> io::Result<()> {
+        return fs::write(filename, self.to_bytes());
+    }
+
+    pub fn load(filename: &str) -> io::Result<Trace> {
+        let bytes = fs::read(filename)?;
+        return Trace::from_bytes(&bytes).ok_or(io::Error::new(io::ErrorKind::InvalidData, "Invalid trace file"));
+    }
+
+    // Header followed by zigzag varints:
+    // ip, relative base, instruction words, loaded values, flags, [address, value], [io value]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = b"ICT1".to_vec();
+        put(&mut out, self.entries.len() as Word);
+        for entry in self.entries.iter() {
+            put(&mut out, entry.state.ip);
+            put(&mut out, entry.state.relative_base);
+            for w in entry.instruction.encode() {
+                put(&mut out, w);
+            }
+            for v in entry.loaded.iter() {
+                put(&mut out, *v);
+            }
+            let flags = match entry.write { Some(_) => 1, None => 0 }
+                + match entry.io { Some(IoEvent::Input(_)) => 2, Some(IoEvent::Output(_)) => 4, None => 0 };
+            put(&mut out, flags);
+            if let Some((address, value)) = entry.write {
+                put(&mut out, address);
+                put(&mut out, value);
+            }
+            match entry.io {
+                Some(IoEvent::Input(v)) | Some(IoEvent::Output(v)) => put(&mut out, v),
+                None => {},
+            }
+        }
+        return out;
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Trace> {
+        if bytes.len() < 4 || &bytes[..4] != b"ICT1" {
+            return None;
+        }
+        let mut pos = 4;
+        let count = get(bytes, &mut pos)?;
+        let mut entries = vec![];
+        for _ in 0..count {
+            let state = ProcessorState { ip: get(bytes, &mut pos)?, relative_base: get(bytes, &mut pos)? };
+            let mut words = vec![get(bytes, &mut pos)?];
+            let op_code = words[0] % 100;
+            let size = match op_code {
+                1 | 2 | 7 | 8 => 4,
+                5 | 6 => 3,
+                3 | 4 | 9 => 2,
+                _ => 1,
+            };
+            for _ in 1..size {
+                words.push(get(bytes, &mut pos)?);
+            }
+            let instruction = try_decode_instruction(&0, &words)?;
+            let mut loaded = vec![];
+            let n_loaded = instruction.params().len() - if instruction.destination().is_some() { 1 } else { 0 };
+            for _ in 0..n_loaded {
+                loaded.push(get(bytes, &mut pos)?);
+            }
+            let flags = get(bytes, &mut pos)?;
+            let write = if flags & 1 != 0 {
+                Some((get(bytes, &mut pos)?, get(bytes, &mut pos)?))
+            } else {
+                None
+            };
+            let io = match flags & 6 {
+                2 => Some(IoEvent::Input(get(bytes, &mut pos)?)),
+                4 => Some(IoEvent::Output(get(bytes, &mut pos)?)),
+                _ => None,
+            };
+            entries.push(TraceEntry { state, instruction, loaded, write, io });
+        }
+        if pos != bytes.len() {
+            return None;
+        }
+        return Some(Trace { entries });
+    }
+}
+
+pub(crate) fn put(out: &mut Vec<u8>, value: Word) {
+    let mut v = ((value << 1) ^ (value >> 63)) as u64;
+    loop {
+        let byte = (v & 0x7f) as u8;
+        v >>= 7;
+        if v == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+pub(crate) fn get(bytes: &[u8], pos: &mut usize) -> Option<Word> {
+    let mut v: u64 = 0;
+    let mut shift = 0;
+    loop {
+        let byte = *bytes.get(*pos)?;
+        *pos += 1;
+        if shift >= 64 {
+            return None;
+        }
+        v |= ((byte & 0x7f) as u64) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+    return Some((v >> 1) as Word ^ -((v & 1) as Word));
+}
+
+// Runs the program again with the recorded input and reports the first step that differs
+pub fn verify(initial: &Memory, trace: &Trace) -> Result<(), Box<Divergence>> {
+    let mut memory = load_program(initial);
+    let mut state = ProcessorState::new();
+    let mut io = BufferIO::new(trace.inputs());
+
+    for (step, expected) in trace.entries.iter().enumerate() {
+        // Check before executing, a different instruction could ask for input that is not there
+        let actual = try_decode_instruction(&state.ip, &memory)
+            .map(|instruction| pending_entry(&memory, &state, instruction));
+        let same_start = match &actual {
+            Some(a) => a.state == expected.state && a.instruction == expected.instruction && a.loaded == expected.loaded,
+            None => false,
+        };
+        if !same_start {
+            return Err(Box::new(Divergence { step, expected: Some(expected.clone()), actual }));
+        }
+
+        let (actual, halted) = match traced_step(&mut memory, &mut state, &mut io) {
+            Ok(result) => result,
+            Err(_) => return Err(Box::new(Divergence { step, expected: Some(expected.clone()), actual: None })),
+        };
+        if actual != *expected {
+            return Err(Box::new(Divergence { step, expected: Some(expected.clone()), actual: Some(actual) }));
+        }
+        if halted {
+            if step + 1 < trace.entries.len() {
+                return Err(Box::new(Divergence { step: step + 1, expected: Some(trace.entries[step + 1].clone()), actual: None }));
+            }
+            return Ok(());
+        }
+    }
+    let step = trace.entries.len();
+    let actual = try_decode_instruction(&state.ip, &memory)
+        .map(|instruction| pending_entry(&memory, &state, instruction));
+    return Err(Box::new(Divergence { step, expected: None, actual }));
+}
+
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04} rb {:<5} {:<28}", self.state.ip, self.state.relative_base, self.instruction.to_string())?;
+        if !self.loaded.is_empty() {
+            let loaded: Vec<String> = self.loaded.iter().map(|v| v.to_string()).collect();
+            write!(f, " load {}", loaded.join(","))?;
+        }
+        if let Some((address, value)) = self.write {
+            write!(f, " mem[{}] = {}", address, value)?;
+        }
+        match self.io {
+            Some(IoEvent::Input(v)) => write!(f, " input {}", v)?,
+            Some(IoEvent::Output(v)) => write!(f, " output {}", v)?,
+            None => {},
+        }
+        return Ok(());
+    }
+}
+
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for entry in self.entries.iter() {
+            writeln!(f, "{}", entry)?;
+        }
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::intmachine::{execute_traced, BufferIO};
+    use crate::trace::{Trace, IoEvent, verify, put, get};
+    use crate::assembler::assemble;
+
+    fn double() -> Vec<i64> {
+        return assemble("
+        loop:   in   [x]
+                mul  [x], 2, [x]
+                out  [x]
+                jt   [x], loop
+                hlt
+        x:      data 0
+        ").unwrap();
+    }
+
+    #[test]
+    fn test_record() {
+        let mut io = BufferIO::new(vec![3, 0]);
+        let (memory, trace) = execute_traced(&double(), &mut io).unwrap();
+        assert_eq!(io.output, vec![6, 0]);
+        assert_eq!(trace.entries.len(), 9);
+        assert_eq!(trace.entries[0].io, Some(IoEvent::Input(3)));
+        assert_eq!(trace.entries[1].loaded, vec![3, 2]);
+        assert_eq!(trace.entries[1].write, Some((12, 6)));
+        assert_eq!(trace.inputs(), vec![3, 0]);
+        assert_eq!(trace.outputs(), vec![6, 0]);
+        assert_eq!(trace.replay(&double(), 2).load(12), Some(6));
+        assert_eq!(trace.replay(&double(), trace.entries.len()), memory);
+    }
+
+    #[test]
+    fn test_serialize() {
+        let mut io = BufferIO::new(vec![-7, 5, 0]);
+        let (_, trace) = execute_traced(&double(), &mut io).unwrap();
+        let bytes = trace.to_bytes();
+        assert_eq!(Trace::from_bytes(&bytes), Some(trace));
+        assert_eq!(Trace::from_bytes(&bytes[..bytes.len() - 1]), None);
+        let mut padded = bytes.clone();
+        padded.push(0);
+        assert_eq!(Trace::from_bytes(&padded), None);
+
+        for v in [0, 1, -1, 63, -64, 1 << 40, std::i64::MAX, std::i64::MIN].iter() {
+            let mut out = vec![];
+            put(&mut out, *v);
+            assert_eq!(get(&out, &mut 0), Some(*v));
+        }
+    }
+
+    #[test]
+    fn test_verify() {
+        let mut io = BufferIO::new(vec![3, 0]);
+        let (_, trace) = execute_traced(&double(), &mut io).unwrap();
+        assert_eq!(verify(&double(), &trace), Ok(()));
+
+        // Multiply by 3 instead
+        let mut changed = double();
+        changed[4] = 3;
+        let divergence = verify(&changed, &trace).unwrap_err();
+        assert_eq!(divergence.step, 1);
+
+        let mut shorter = trace.clone();
+        shorter.entries.truncate(4);
+        assert_eq!(verify(&double(), &shorter).unwrap_err().step, 4);
+    }
+}
@@ -0,0 +1,336 @@
+// Word types for IntMachine, for programs whose values outgrow i64.
+//
+// Values can be i64, i128, BigInt or Promoting, which is an i64 until a result no longer fits
+// and, with OverflowPolicy::Promote, a BigInt after that. Addresses, op codes and the relative base still have to fit in a Word.
+// Decoding is shared by all word types, see WordType::decode.
+
+use crate::intmachine::{Instruction, IntcodeError, PagedMemory, Word, WordView, check_params, decode_instruction};
+use num::{BigInt, ToPrimitive};
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+// What happens when a result does not fit the word, Trap by default
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum OverflowPolicy {
+    // Two's complement wrap around
+    Wrap,
+    // Fail with IntcodeError::Overflow
+    Trap,
+    // Grow the value, word types that can not grow trap
+    Promote,
+}
+
+impl Default for OverflowPolicy {
+    fn default() -> OverflowPolicy {
+        return OverflowPolicy::Trap;
+    }
+}
+
+impl OverflowPolicy {
+    // None if the result traps
+    pub fn add<W: WordType>(&self, a: &W, b: &W) -> Option<W> {
+        return match self {
+            OverflowPolicy::Wrap => Some(a.wrapping_add(b)),
+            OverflowPolicy::Trap => a.checked_add(b),
+            OverflowPolicy::Promote => a.promoting_add(b),
+        }
+    }
+
+    pub fn mul<W: WordType>(&self, a: &W, b: &W) -> Option<W> {
+        return match self {
+            OverflowPolicy::Wrap => Some(a.wrapping_mul(b)),
+            OverflowPolicy::Trap => a.checked_mul(b),
+            OverflowPolicy::Promote => a.promoting_mul(b),
+        }
+    }
+}
+
+pub trait WordType: Clone + fmt::Debug + fmt::Display + Eq + Ord + From<i64> {
+    fn checked_add(&self, other: &Self) -> Option<Self>;
+    fn checked_mul(&self, other: &Self) -> Option<Self>;
+    fn wrapping_add(&self, other: &Self) -> Self;
+    fn wrapping_mul(&self, other: &Self) -> Self;
+    // None if the value does not fit an address
+    fn to_word(&self) -> Option<Word>;
+
+    fn promoting_add(&self, other: &Self) -> Option<Self> {
+        return self.checked_add(other);
+    }
+
+    fn promoting_mul(&self, other: &Self) -> Option<Self> {
+        return self.checked_mul(other);
+    }
+
+    // Decodes the instruction at ip, parameters that do not fit a Word fail with Overflow
+    // unless they are immediates
+    fn decode(ip: &Word, memory: &PagedMemory<Self>) -> Result<Instruction, IntcodeError> {
+        let instruction = decode_instruction(ip, &WordView(memory))?;
+        check_params(ip, &instruction.params(), memory, true)?;
+        return Ok(instruction);
+    }
+
+    // Immediate parameter at address, decoded as value
+    fn immediate(memory: &PagedMemory<Self>, address: Word, value: Word) -> Self {
+        return memory.load(address).unwrap_or_else(|| Self::from(value));
+    }
+}
+
+// Decodes memory directly, there is nothing to cut
+impl WordType for i64 {
+    fn checked_add(&self, other: &i64) -> Option<i64> { return i64::checked_add(*self, *other); }
+    fn checked_mul(&self, other: &i64) -> Option<i64> { return i64::checked_mul(*self, *other); }
+    fn wrapping_add(&self, other: &i64) -> i64 { return i64::wrapping_add(*self, *other); }
+    fn wrapping_mul(&self, other: &i64) -> i64 { return i64::wrapping_mul(*self, *other); }
+    fn to_word(&self) -> Option<Word> { return Some(*self); }
+    fn decode(ip: &Word, memory: &PagedMemory) -> Result<Instruction, IntcodeError> { return decode_instruction(ip, memory); }
+    fn immediate(_memory: &PagedMemory, _address: Word, value: Word) -> i64 { return value; }
+}
+
+impl WordType for i128 {
+    fn checked_add(&self, other: &i128) -> Option<i128> { return i128::checked_add(*self, *other); }
+    fn checked_mul(&self, other: &i128) -> Option<i128> { return i128::checked_mul(*self, *other); }
+    fn wrapping_add(&self, other: &i128) -> i128 { return i128::wrapping_add(*self, *other); }
+    fn wrapping_mul(&self, other: &i128) -> i128 { return i128::wrapping_mul(*self, *other); }
+    fn to_word(&self) -> Option<Word> { return self.to_i64(); }
+}
+
+// Never overflows, so the policy does not matter
+impl WordType for BigInt {
+    fn checked_add(&self, other: &BigInt) -> Option<BigInt> { return Some(self + other); }
+    fn checked_mul(&self, other: &BigInt) -> Option<BigInt> { return Some(self * other); }
+    fn wrapping_add(&self, other: &BigInt) -> BigInt { return self + other; }
+    fn wrapping_mul(&self, other: &BigInt) -> BigInt { return self * other; }
+    fn to_word(&self) -> Option<Word> { return self.to_i64(); }
+}
+
+// An i64 that is promoted to a BigInt when a result does not fit and the policy is Promote.
+// Trap and Wrap treat it like an i64. Big values that fit an i64 again are demoted, so equal
+// values always have the same variant.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Promoting {
+    Small(i64),
+    Big(BigInt),
+}
+
+impl Promoting {
+    fn from_big(value: BigInt) -> Promoting {
+        return match value.to_i64() {
+            Some(v) => Promoting::Small(v),
+            None => Promoting::Big(value),
+        }
+    }
+
+    pub fn to_big(&self) -> BigInt {
+        return match self {
+            Promoting::Small(v) => BigInt::from(*v),
+            Promoting::Big(v) => v.clone(),
+        }
+    }
+
+    fn small(self) -> Option<Promoting> {
+        return match self {
+            Promoting::Small(_) => Some(self),
+            Promoting::Big(_) => None,
+        }
+    }
+
+    // Two's complement reduction to 64 bits
+    fn wrap(value: BigInt) -> Promoting {
+        let low = (value & BigInt::from(u64::MAX)).to_u64().unwrap();
+        return Promoting::Small(low as i64);
+    }
+
+    pub fn is_promoted(&self) -> bool {
+        return match self {
+            Promoting::Small(_) => false,
+            Promoting::Big(_) => true,
+        }
+    }
+}
+
+impl From<i64> for Promoting {
+    fn from(value: i64) -> Promoting {
+        return Promoting::Small(value);
+    }
+}
+
+impl Ord for Promoting {
+    fn cmp(&self, other: &Promoting) -> Ordering {
+        return match (self, other) {
+            (Promoting::Small(a), Promoting::Small(b)) => a.cmp(b),
+            _ => self.to_big().cmp(&other.to_big()),
+        }
+    }
+}
+
+impl PartialOrd for Promoting {
+    fn partial_cmp(&self, other: &Promoting) -> Option<Ordering> {
+        return Some(self.cmp(other));
+    }
+}
+
+impl fmt::Display for Promoting {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            Promoting::Small(v) => write!(f, "{}", v),
+            Promoting::Big(v) => write!(f, "{}", v),
+        }
+    }
+}
+
+// Two small values stay on i64, only a Big operand goes through BigInt
+impl WordType for Promoting {
+    fn checked_add(&self, other: &Promoting) -> Option<Promoting> {
+        if let (Promoting::Small(a), Promoting::Small(b)) = (self, other) {
+            return i64::checked_add(*a, *b).map(Promoting::Small);
+        }
+        return Promoting::from_big(self.to_big() + other.to_big()).small();
+    }
+
+    fn checked_mul(&self, other: &Promoting) -> Option<Promoting> {
+        if let (Promoting::Small(a), Promoting::Small(b)) = (self, other) {
+            return i64::checked_mul(*a, *b).map(Promoting::Small);
+        }
+        return Promoting::from_big(self.to_big() * other.to_big()).small();
+    }
+
+    fn wrapping_add(&self, other: &Promoting) -> Promoting {
+        if let (Promoting::Small(a), Promoting::Small(b)) = (self, other) {
+            return Promoting::Small(i64::wrapping_add(*a, *b));
+        }
+        return Promoting::wrap(self.to_big() + other.to_big());
+    }
+
+    fn wrapping_mul(&self, other: &Promoting) -> Promoting {
+        if let (Promoting::Small(a), Promoting::Small(b)) = (self, other) {
+            return Promoting::Small(i64::wrapping_mul(*a, *b));
+        }
+        return Promoting::wrap(self.to_big() * other.to_big());
+    }
+
+    fn to_word(&self) -> Option<Word> {
+        return match self {
+            Promoting::Small(v) => Some(*v),
+            Promoting::Big(_) => None,
+        }
+    }
+
+    fn promoting_add(&self, other: &Promoting) -> Option<Promoting> {
+        if let (Promoting::Small(a), Promoting::Small(b)) = (self, other) {
+            if let Some(v) = i64::checked_add(*a, *b) {
+                return Some(Promoting::Small(v));
+            }
+        }
+        return Some(Promoting::from_big(self.to_big() + other.to_big()));
+    }
+
+    fn promoting_mul(&self, other: &Promoting) -> Option<Promoting> {
+        if let (Promoting::Small(a), Promoting::Small(b)) = (self, other) {
+            if let Some(v) = i64::checked_mul(*a, *b) {
+                return Some(Promoting::Small(v));
+            }
+        }
+        return Some(Promoting::from_big(self.to_big() * other.to_big()));
+    }
+}
+
+impl FromStr for Promoting {
+    type Err = <BigInt as FromStr>::Err;
+
+    fn from_str(s: &str) -> Result<Promoting, Self::Err> {
+        return Ok(Promoting::from_big(BigInt::from_str(s)?));
+    }
+}
+
+// Parses a comma separated program, None if a value can not be parsed
+pub fn parse_program<W: WordType + FromStr>(s: &str) -> Option<Vec<W>> {
+    return s.trim().split(',').map(|x| x.trim().parse::<W>().ok()).collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::wide::{OverflowPolicy, Promoting, WordType, parse_program};
+    use crate::intmachine::{IntMachine, IntcodeError, Status};
+    use num::BigInt;
+    use std::str::FromStr;
+
+    // Squares the constant twice: 34915192349151923^4 needs about 220 bits
+    fn squares() -> Vec<i64> {
+        return vec![1102,34915192349151923,34915192349151923,13,4,13,2,13,13,13,4,13,99,0];
+    }
+
+    #[test]
+    fn test_i64() {
+        let mut machine: IntMachine<i64> = IntMachine::from_program(&squares());
+        assert_eq!(machine.run(), Err(IntcodeError::Overflow { ip: 0, word: 1102 }));
+
+        let mut machine: IntMachine<i64> = IntMachine::from_program(&squares());
+        machine.set_overflow_policy(OverflowPolicy::Wrap);
+        assert_eq!(machine.run(), Ok(Status::Output(34915192349151923i64.wrapping_mul(34915192349151923))));
+
+        let mut machine: IntMachine<i64> = IntMachine::from_program(&vec![3,9,8,9,10,9,4,9,99,-1,8]);
+        machine.push_input(8);
+        assert_eq!(machine.run_to_end(), Ok(vec![1]));
+    }
+
+    #[test]
+    fn test_i128() {
+        let square = 34915192349151923i128 * 34915192349151923;
+        let mut machine: IntMachine<i128> = IntMachine::from_program(&squares());
+        assert_eq!(machine.run(), Ok(Status::Output(square)));
+        assert_eq!(machine.run(), Err(IntcodeError::Overflow { ip: 6, word: 2 }));
+    }
+
+    #[test]
+    fn test_big() {
+        let square = BigInt::from(34915192349151923i64) * BigInt::from(34915192349151923i64);
+        let mut machine: IntMachine<BigInt> = IntMachine::from_program(&squares());
+        assert_eq!(machine.run_to_end(), Ok(vec![square.clone(), &square * &square]));
+
+        let mut machine: IntMachine<Promoting> = IntMachine::from_program(&squares());
+        machine.set_overflow_policy(OverflowPolicy::Promote);
+        let output = machine.run_to_end().unwrap();
+        assert_eq!(output.iter().map(|v| v.to_big()).collect::<Vec<_>>(), vec![square.clone(), &square * &square]);
+        assert!(output[1].is_promoted());
+        assert_eq!(output[1].to_string(), (&square * &square).to_string());
+        assert_eq!(Promoting::from(-5).wrapping_mul(&Promoting::from(2)), Promoting::Small(-10));
+        assert_eq!(Promoting::from(i64::MIN).wrapping_add(&Promoting::from(-1)), Promoting::Small(i64::MAX));
+        assert_eq!(Promoting::from(i64::MAX).checked_add(&Promoting::from(1)), None);
+        assert_eq!(Promoting::from(i64::MAX).checked_mul(&Promoting::from(2)), None);
+        // A Big operand can still give a small result
+        let above = Promoting::Big(BigInt::from(i64::MAX) + 1);
+        assert_eq!(above.checked_add(&Promoting::from(-1)), Some(Promoting::Small(i64::MAX)));
+        assert_eq!(above.checked_mul(&Promoting::from(2)), None);
+        assert_eq!(above.wrapping_mul(&Promoting::from(2)), Promoting::Small(0));
+        assert!(Promoting::Big(-&square) < Promoting::Small(0));
+    }
+
+    #[test]
+    fn test_parse() {
+        // Outputs a value that does not fit in i64
+        let program: Vec<BigInt> = parse_program("104,100000000000000000000000,99").unwrap();
+        let mut machine = IntMachine::from_words(&program);
+        assert_eq!(machine.run_to_end(), Ok(vec![BigInt::from_str("100000000000000000000000").unwrap()]));
+        assert_eq!(parse_program::<i64>("104,100000000000000000000000,99"), None);
+    }
+
+    #[test]
+    fn test_addresses() {
+        // Values that do not fit a Word can not be used as addresses, jump targets or offsets
+        let program: Vec<BigInt> = parse_program("1,100000000000000000000000,0,0,99").unwrap();
+        assert_eq!(IntMachine::from_words(&program).run(), Err(IntcodeError::Overflow { ip: 0, word: 1 }));
+        let program: Vec<BigInt> = parse_program("1105,1,100000000000000000000000").unwrap();
+        assert_eq!(IntMachine::from_words(&program).run(), Err(IntcodeError::Overflow { ip: 0, word: 1105 }));
+        let program: Vec<i128> = parse_program("109,100000000000000000000000,99").unwrap();
+        assert_eq!(IntMachine::from_words(&program).run(), Err(IntcodeError::Overflow { ip: 0, word: 109 }));
+
+        // Input and the code tracker work for any word type
+        let mut machine: IntMachine<i128> = IntMachine::from_program(&vec![3,1,99]);
+        machine.track_code();
+        assert_eq!(machine.run(), Ok(Status::NeedsInput));
+        machine.push_input(1 << 100);
+        assert_eq!(machine.run(), Ok(Status::Halted));
+        assert_eq!(machine.code_tracker().unwrap().code_writes()[0].value, 1 << 100);
+    }
+}